@@ -0,0 +1,424 @@
+use super::shapes::{Aabb, Ray, Shape};
+use super::Float3;
+
+// Relative costs used by the surface area heuristic.
+const TRAVERSAL_COST: f64 = 1.0;
+const INTERSECTION_COST: f64 = 1.0;
+
+const BIN_COUNT: usize = 12;
+const MAX_LEAF_SIZE: usize = 8;
+
+#[derive(Debug, Copy, Clone)]
+struct Node {
+    bounds: Aabb,
+
+    // For a leaf `first` is the index of the first primitive & `count` how
+    // many there are. For an interior node `count` is 0, the left child is
+    // the next node and `first` is the index of the right child.
+    first: usize,
+    count: usize,
+}
+
+struct BuildPrimitive {
+    index: usize,
+    bounds: Aabb,
+    center: Float3,
+}
+
+/// Bounding volume hierarchy over a list of shapes, built with a binned
+/// surface area heuristic. Shapes are referred to by their index in the list.
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    primitives: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn new<T: Shape>(shapes: &[T]) -> Self {
+        let mut build: Vec<BuildPrimitive> = shapes
+            .iter()
+            .enumerate()
+            .map(|(index, shape)| {
+                let bounds = shape.bounds();
+
+                BuildPrimitive {
+                    index,
+                    bounds,
+                    center: bounds.center(),
+                }
+            })
+            .collect();
+
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(build.len() * 2),
+            primitives: Vec::with_capacity(build.len()),
+        };
+
+        if !build.is_empty() {
+            bvh.build_node(&mut build);
+        }
+
+        bvh
+    }
+
+    fn build_node(&mut self, build: &mut [BuildPrimitive]) -> usize {
+        let mut bounds = Aabb::empty();
+        let mut center_bounds = Aabb::empty();
+
+        for primitive in build.iter() {
+            bounds.grow(&primitive.bounds);
+            center_bounds.grow_point(&primitive.center);
+        }
+
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            bounds,
+            first: self.primitives.len(),
+            count: build.len(),
+        });
+
+        let split = if build.len() > 1 {
+            find_split(build, &bounds, &center_bounds)
+        } else {
+            None
+        };
+
+        match split {
+            Some(mid) => {
+                let (left, right) = build.split_at_mut(mid);
+
+                self.build_node(left);
+                let right_index = self.build_node(right);
+
+                self.nodes[index].first = right_index;
+                self.nodes[index].count = 0;
+            }
+            None => {
+                self.primitives.extend(build.iter().map(|primitive| primitive.index));
+            }
+        }
+
+        index
+    }
+
+    /// Walks the hierarchy front to back calling `hit` with the index of every
+    /// shape whose bounds the ray passes through before `max_t`.
+    ///
+    /// `hit` returns the distance of its intersection if there is one, further
+    /// primitives are then only tested up to that distance. If `any_hit` is
+    /// set the walk stops at the first intersection found instead.
+    pub fn traverse<F>(&self, ray: &Ray, max_t: f64, any_hit: bool, mut hit: F)
+    where
+        F: FnMut(usize, f64) -> Option<f64>,
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let inverse_direction = Float3::new(
+            1.0 / ray.direction.x,
+            1.0 / ray.direction.y,
+            1.0 / ray.direction.z,
+        );
+
+        let mut max_t = max_t;
+        let mut stack: Vec<usize> = Vec::with_capacity(64);
+
+        if self.nodes[0].bounds.ray_entry(ray, &inverse_direction, max_t).is_some() {
+            stack.push(0);
+        }
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+
+            // The closest hit may have moved since this node was pushed.
+            if node.bounds.ray_entry(ray, &inverse_direction, max_t).is_none() {
+                continue;
+            }
+
+            if node.count > 0 {
+                for primitive in self.primitives[node.first..(node.first + node.count)].iter() {
+                    if let Some(t) = hit(*primitive, max_t) {
+                        if any_hit {
+                            return;
+                        }
+
+                        max_t = t;
+                    }
+                }
+
+                continue;
+            }
+
+            let left = index + 1;
+            let right = node.first;
+
+            let left_t = self.nodes[left].bounds.ray_entry(ray, &inverse_direction, max_t);
+            let right_t = self.nodes[right].bounds.ray_entry(ray, &inverse_direction, max_t);
+
+            // Push the farther child first so the nearer one is visited first.
+            match (left_t, right_t) {
+                (Some(l), Some(r)) => {
+                    if l < r {
+                        stack.push(right);
+                        stack.push(left);
+                    } else {
+                        stack.push(left);
+                        stack.push(right);
+                    }
+                }
+                (Some(_), None) => stack.push(left),
+                (None, Some(_)) => stack.push(right),
+                (None, None) => {}
+            }
+        }
+    }
+}
+
+/// Picks the cheapest split plane according to the surface area heuristic,
+/// partitions `build` around it & returns the index of the first primitive
+/// on the right side. Returns None if it's cheaper to make a leaf.
+fn find_split(build: &mut [BuildPrimitive], bounds: &Aabb, center_bounds: &Aabb) -> Option<usize> {
+    let extent = center_bounds.max - center_bounds.min;
+    let axis = extent.imax();
+
+    if extent[axis] <= 0.0 || !extent[axis].is_finite() {
+        // Every center is in the same spot, no plane can separate them.
+        return None;
+    }
+
+    let bin_of = |center: &Float3| -> usize {
+        let offset = (center[axis] - center_bounds.min[axis]) / extent[axis];
+        usize::min(BIN_COUNT - 1, (offset * BIN_COUNT as f64) as usize)
+    };
+
+    let mut bin_bounds = [Aabb::empty(); BIN_COUNT];
+    let mut bin_counts = [0usize; BIN_COUNT];
+
+    for primitive in build.iter() {
+        let bin = bin_of(&primitive.center);
+        bin_bounds[bin].grow(&primitive.bounds);
+        bin_counts[bin] += 1;
+    }
+
+    // Cost of splitting after each bin, sweeping from both ends.
+    let mut right_areas = [0.0; BIN_COUNT];
+    let mut right_counts = [0usize; BIN_COUNT];
+    let mut accumulated = Aabb::empty();
+    let mut count = 0;
+
+    for bin in (1..BIN_COUNT).rev() {
+        accumulated.grow(&bin_bounds[bin]);
+        count += bin_counts[bin];
+        right_areas[bin] = accumulated.surface_area();
+        right_counts[bin] = count;
+    }
+
+    let mut best: Option<(f64, usize)> = None;
+    let mut accumulated = Aabb::empty();
+    let mut count = 0;
+
+    for bin in 0..(BIN_COUNT - 1) {
+        accumulated.grow(&bin_bounds[bin]);
+        count += bin_counts[bin];
+
+        if count == 0 || right_counts[bin + 1] == 0 {
+            continue;
+        }
+
+        let cost = (accumulated.surface_area() * count as f64)
+            + (right_areas[bin + 1] * right_counts[bin + 1] as f64);
+
+        if best.map_or(true, |(best_cost, _)| cost < best_cost) {
+            best = Some((cost, bin));
+        }
+    }
+
+    let (best_cost, best_bin) = best?;
+
+    let parent_area = bounds.surface_area();
+    let split_cost = if parent_area.is_finite() && parent_area > 0.0 {
+        TRAVERSAL_COST + (INTERSECTION_COST * best_cost / parent_area)
+    } else {
+        // Unbounded shapes make the heuristic meaningless, split anyway.
+        0.0
+    };
+    let leaf_cost = INTERSECTION_COST * build.len() as f64;
+
+    if build.len() <= MAX_LEAF_SIZE && leaf_cost <= split_cost {
+        return None;
+    }
+
+    // Partition in place around the chosen bin.
+    let mut mid = 0;
+    for i in 0..build.len() {
+        if bin_of(&build[i].center) <= best_bin {
+            build.swap(i, mid);
+            mid += 1;
+        }
+    }
+
+    Some(mid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::{Material, Sphere};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn material() -> Material {
+        Material {
+            diffuse: Float3::new(1.0, 1.0, 1.0),
+            specular_coefficient: 0.0,
+            specular_power: 1.0,
+            attenuation: Float3::new(1.0, 1.0, 1.0),
+            electric_permittivity: 1.0,
+            magnetic_permeability: 1.0,
+            index_of_refraction: 1.0,
+            emission: Float3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn random_point(rng: &mut StdRng, size: f64) -> Float3 {
+        Float3::new(
+            rng.gen_range(-size, size),
+            rng.gen_range(-size, size),
+            rng.gen_range(-size, size),
+        )
+    }
+
+    fn random_spheres(rng: &mut StdRng, count: usize) -> Vec<Sphere> {
+        (0..count)
+            .map(|_| Sphere {
+                center: random_point(rng, 10.0),
+                radius: rng.gen_range(0.1, 1.0),
+                material: material(),
+            })
+            .collect()
+    }
+
+    fn random_ray(rng: &mut StdRng) -> Ray {
+        let origin = random_point(rng, 12.0);
+        let target = random_point(rng, 8.0);
+
+        Ray {
+            origin,
+            direction: (target - origin).normalize(),
+        }
+    }
+
+    fn closest_brute_force(spheres: &[Sphere], ray: &Ray, max_t: f64) -> Option<(usize, f64)> {
+        let mut closest: Option<(usize, f64)> = None;
+
+        for (index, sphere) in spheres.iter().enumerate() {
+            let max_t = closest.map_or(max_t, |(_, t)| t);
+
+            if let Some(intersection) = sphere.intersect(ray, max_t) {
+                closest = Some((index, intersection.t));
+            }
+        }
+
+        closest
+    }
+
+    fn closest_bvh(bvh: &Bvh, spheres: &[Sphere], ray: &Ray, max_t: f64) -> Option<(usize, f64)> {
+        let mut closest: Option<(usize, f64)> = None;
+
+        bvh.traverse(ray, max_t, false, |index, max_t| {
+            let intersection = spheres[index].intersect(ray, max_t)?;
+            closest = Some((index, intersection.t));
+
+            Some(intersection.t)
+        });
+
+        closest
+    }
+
+    #[test]
+    fn closest_hit_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for &count in [1, 2, 7, 50, 500].iter() {
+            let spheres = random_spheres(&mut rng, count);
+            let bvh = Bvh::new(&spheres);
+
+            for _ in 0..2000 {
+                let ray = random_ray(&mut rng);
+                let max_t = if rng.gen_bool(0.5) { f64::INFINITY } else { rng.gen_range(1.0, 30.0) };
+
+                let expected = closest_brute_force(&spheres, &ray, max_t);
+                let found = closest_bvh(&bvh, &spheres, &ray, max_t);
+
+                match (expected, found) {
+                    (None, None) => {}
+                    (Some((_, expected_t)), Some((_, found_t))) => assert_eq!(expected_t, found_t),
+                    _ => panic!("{} spheres: expected {:?}, found {:?}", count, expected, found),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn any_hit_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let spheres = random_spheres(&mut rng, 200);
+        let bvh = Bvh::new(&spheres);
+
+        for _ in 0..2000 {
+            let ray = random_ray(&mut rng);
+            let max_t = rng.gen_range(1.0, 30.0);
+
+            let mut calls = 0;
+            let mut hit = false;
+            bvh.traverse(&ray, max_t, true, |index, max_t| {
+                assert!(!hit, "traversal went on after a hit");
+                calls += 1;
+
+                let t = spheres[index].intersect(&ray, max_t)?.t;
+                hit = true;
+
+                Some(t)
+            });
+
+            assert!(calls <= spheres.len());
+            assert_eq!(hit, closest_brute_force(&spheres, &ray, max_t).is_some());
+        }
+    }
+
+    #[test]
+    fn every_shape_is_in_one_leaf() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let spheres = random_spheres(&mut rng, 300);
+        let bvh = Bvh::new(&spheres);
+
+        let mut primitives = bvh.primitives.clone();
+        primitives.sort_unstable();
+        assert_eq!(primitives, (0..spheres.len()).collect::<Vec<_>>());
+
+        for node in bvh.nodes.iter().filter(|node| node.count > 0) {
+            assert!(node.count <= MAX_LEAF_SIZE);
+
+            for &primitive in bvh.primitives[node.first..(node.first + node.count)].iter() {
+                let bounds = spheres[primitive].bounds();
+
+                for axis in 0..3 {
+                    assert!(node.bounds.min[axis] <= bounds.min[axis]);
+                    assert!(node.bounds.max[axis] >= bounds.max[axis]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_hierarchy_has_no_hits() {
+        let bvh = Bvh::new::<Sphere>(&[]);
+        let ray = Ray {
+            origin: Float3::new(0.0, 0.0, 0.0),
+            direction: Float3::new(0.0, 0.0, -1.0),
+        };
+
+        bvh.traverse(&ray, f64::INFINITY, false, |_, _| panic!("empty hierarchy visited a shape"));
+    }
+}
@@ -0,0 +1,496 @@
+use serde::{Serialize, Deserialize};
+
+use std::f64::consts::PI;
+
+use super::sampler::concentric_disk;
+use super::shapes::Ray;
+use super::Float3;
+
+fn default_up() -> Float3 {
+    Float3::new(0.0, 1.0, 0.0)
+}
+
+fn is_default_up(up: &Float3) -> bool {
+    *up == default_up()
+}
+
+/// Describes the camera by where it is and what it's looking at, in scene
+/// files this is the `camera` object.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct LookAt {
+    pub eye: Float3,
+    pub target: Float3,
+
+    /// Roughly which way is up, it doesn't need to be perpendicular to the
+    /// view direction. Defaults to +y.
+    #[serde(default = "default_up", skip_serializing_if = "is_default_up")]
+    pub up: Float3,
+
+    /// Vertical field of view in degrees.
+    pub fov: f64,
+
+    /// Width / height of the view, defaults to that of the image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aspect_ratio: Option<f64>,
+}
+
+impl LookAt {
+    /// Describes what's wrong if a camera can't be made from this, along with
+    /// the field at fault.
+    pub fn problem(&self) -> Option<(&'static str, String)> {
+        if !(self.fov > 0.0 && self.fov < 180.0) {
+            return Some(("fov", format!("must be between 0 and 180 degrees, found {}", self.fov)));
+        }
+
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            if aspect_ratio <= 0.0 || !aspect_ratio.is_finite() {
+                return Some(("aspect_ratio", format!("must be greater than zero, found {}", aspect_ratio)));
+            }
+        }
+
+        let forward = self.target - self.eye;
+
+        if forward.norm() == 0.0 {
+            return Some(("target", "is the same point as `eye`".to_string()));
+        }
+
+        if forward.normalize().cross(&self.up).norm() < 1e-9 {
+            return Some(("up", "is parallel to the view direction".to_string()));
+        }
+
+        None
+    }
+}
+
+fn default_aperture() -> Aperture {
+    Aperture::Disk
+}
+
+fn is_default_aperture(aperture: &Aperture) -> bool {
+    *aperture == default_aperture()
+}
+
+/// Shape of the lens opening, this is the shape out of focus highlights take.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Aperture {
+    Disk,
+    /// A regular polygon like the opening of an iris with `blades` blades.
+    /// `rotation` is in degrees.
+    Polygon {
+        blades: u32,
+        #[serde(default, skip_serializing_if = "crate::is_default")]
+        rotation: f64,
+    },
+}
+
+impl Aperture {
+    /// Maps a point in [0, 1)² to a point on the aperture, which fits in the
+    /// unit circle. Evenly spread samples stay evenly spread.
+    pub fn sample(&self, u: f64, v: f64) -> (f64, f64) {
+        match *self {
+            Aperture::Disk => concentric_disk(u, v),
+            Aperture::Polygon { blades, rotation } => {
+                // Pick one of the triangles between the center and an edge,
+                // then a point in it.
+                let blades = blades.max(3) as f64;
+                let sector = (u * blades).floor().min(blades - 1.0);
+                let u = (u * blades) - sector;
+
+                let angle = |i: f64| rotation.to_radians() + (2.0 * PI * i / blades);
+                let (a, b) = (angle(sector), angle(sector + 1.0));
+
+                let scale = u.sqrt();
+                let x = scale * (((1.0 - v) * a.cos()) + (v * b.cos()));
+                let y = scale * (((1.0 - v) * a.sin()) + (v * b.sin()));
+
+                (x, y)
+            }
+        }
+    }
+}
+
+/// A thin lens, points `focus_distance` in front of the eye are sharp and
+/// everything else is blurred by how far it is from there.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Lens {
+    pub aperture_radius: f64,
+    /// Measured along the view direction.
+    pub focus_distance: f64,
+    #[serde(default = "default_aperture", skip_serializing_if = "is_default_aperture")]
+    pub aperture: Aperture,
+}
+
+impl Lens {
+    /// Same as `LookAt::problem`.
+    pub fn problem(&self) -> Option<(&'static str, String)> {
+        if self.aperture_radius < 0.0 || !self.aperture_radius.is_finite() {
+            return Some((
+                "aperture_radius",
+                format!("can't be negative, found {}", self.aperture_radius),
+            ));
+        }
+
+        if self.focus_distance <= 0.0 || !self.focus_distance.is_finite() {
+            return Some((
+                "focus_distance",
+                format!("must be greater than zero, found {}", self.focus_distance),
+            ));
+        }
+
+        if let Aperture::Polygon { blades, .. } = self.aperture {
+            if blades < 3 {
+                return Some(("aperture", format!("needs at least 3 blades, found {}", blades)));
+            }
+        }
+
+        None
+    }
+}
+
+/// How directions from the camera map to points in the image.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum Projection {
+    /// A regular pinhole camera, straight lines stay straight.
+    #[default]
+    Perspective,
+    /// Every ray points the same way so there's no perspective, `height` is
+    /// how tall the view is in scene units.
+    Orthographic { height: f64 },
+    /// Equidistant fisheye, the distance from the center of the image is
+    /// proportional to the angle from the view direction. `fov` is the angle
+    /// in degrees across the image circle, it can go up to 360.
+    Fisheye { fov: f64 },
+    /// The full sphere of directions around the eye. Longitude goes across
+    /// the image and latitude up it, the view direction is in the center.
+    Equirectangular,
+}
+
+impl Projection {
+    /// Same as `LookAt::problem`.
+    pub fn problem(&self) -> Option<(&'static str, String)> {
+        match *self {
+            Projection::Orthographic { height } if height <= 0.0 || !height.is_finite() => {
+                Some(("height", format!("must be greater than zero, found {}", height)))
+            }
+            Projection::Fisheye { fov } if !(fov > 0.0 && fov <= 360.0) => {
+                Some(("fov", format!("must be between 0 and 360 degrees, found {}", fov)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The camera as used when rendering. Rays leave `eye` through a viewport
+/// centered on `viewport_origin` that extends one axis length either side.
+#[derive(Debug, Copy, Clone)]
+pub struct Camera {
+    pub eye: Float3,
+    pub viewport_origin: Float3,
+    pub viewport_x_axis: Float3,
+    pub viewport_y_axis: Float3,
+
+    /// Without a lens every ray starts at `eye` and everything is in focus.
+    pub lens: Option<Lens>,
+    pub projection: Projection,
+}
+
+impl Camera {
+    /// `aspect_ratio` is used when `look_at` doesn't have one.
+    pub fn from_look_at(look_at: &LookAt, aspect_ratio: f64) -> Self {
+        let aspect_ratio = look_at.aspect_ratio.unwrap_or(aspect_ratio);
+
+        let forward = (look_at.target - look_at.eye).normalize();
+        let right = forward.cross(&look_at.up).normalize();
+        let up = right.cross(&forward);
+
+        // The viewport is placed 1 unit in front of the eye.
+        let half_height = (look_at.fov.to_radians() * 0.5).tan();
+        let half_width = half_height * aspect_ratio;
+
+        Camera {
+            eye: look_at.eye,
+            viewport_origin: look_at.eye + forward,
+            viewport_x_axis: right * half_width,
+            viewport_y_axis: up * half_height,
+            lens: None,
+            projection: Projection::Perspective,
+        }
+    }
+
+    /// Moves the eye `offset` along the viewport's x axis for stereo. With a
+    /// perspective projection the viewport is shifted too, rather than turning
+    /// the camera, so both eyes line up `convergence` in front of the camera.
+    pub fn shifted(&self, offset: f64, convergence: f64) -> Camera {
+        let right = self.viewport_x_axis.normalize() * offset;
+
+        let viewport_shift = match self.projection {
+            Projection::Perspective => {
+                let distance = (self.viewport_origin - self.eye).norm();
+
+                right * (1.0 - (distance / convergence))
+            }
+            _ => right,
+        };
+
+        Camera {
+            eye: self.eye + right,
+            viewport_origin: self.viewport_origin + viewport_shift,
+            ..*self
+        }
+    }
+
+    /// `x` & `y` go from -1 to 1 across the viewport. `lens_sample` is in
+    /// [0, 1)² and picks where on the lens the ray starts. Returns `None` for
+    /// points outside the image circle of a fisheye.
+    pub fn ray(&self, x: f64, y: f64, lens_sample: (f64, f64)) -> Option<Ray> {
+        let forward = (self.viewport_origin - self.eye).normalize();
+        let right = self.viewport_x_axis.normalize();
+        let up = self.viewport_y_axis.normalize();
+        let aspect_ratio = self.viewport_x_axis.norm() / self.viewport_y_axis.norm();
+
+        let ray = match self.projection {
+            Projection::Perspective => {
+                let viewport_position = self.viewport_origin + (x * self.viewport_x_axis) + (y * self.viewport_y_axis);
+
+                Ray {
+                    origin: self.eye,
+                    direction: (viewport_position - self.eye).normalize(),
+                }
+            }
+            Projection::Orthographic { height } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * aspect_ratio;
+
+                Ray {
+                    origin: self.eye + (right * (x * half_width)) + (up * (y * half_height)),
+                    direction: forward,
+                }
+            }
+            Projection::Fisheye { fov } => {
+                // Keep the image circle round and fit it to the shorter side.
+                let (x, y) = if aspect_ratio > 1.0 {
+                    (x * aspect_ratio, y)
+                } else {
+                    (x, y / aspect_ratio)
+                };
+
+                let r = ((x * x) + (y * y)).sqrt();
+
+                if r > 1.0 {
+                    return None;
+                }
+
+                let theta = r * (fov.to_radians() * 0.5);
+                let phi = y.atan2(x);
+
+                Ray {
+                    origin: self.eye,
+                    direction: (forward * theta.cos())
+                        + (right * (theta.sin() * phi.cos()))
+                        + (up * (theta.sin() * phi.sin())),
+                }
+            }
+            Projection::Equirectangular => {
+                let longitude = x * PI;
+                let latitude = y * (PI * 0.5);
+
+                Ray {
+                    origin: self.eye,
+                    direction: (forward * (latitude.cos() * longitude.cos()))
+                        + (right * (latitude.cos() * longitude.sin()))
+                        + (up * latitude.sin()),
+                }
+            }
+        };
+
+        let lens = match (self.lens, self.projection) {
+            (_, Projection::Equirectangular) => return Some(ray),
+            (Some(lens), _) if lens.aperture_radius > 0.0 => lens,
+            _ => return Some(ray),
+        };
+
+        // Every ray through this point in the image meets at the same point
+        // on the plane of focus. Fisheye rays pointing sideways or backwards
+        // never reach it and are left sharp.
+        let d_dot_f = ray.direction.dot(&forward);
+
+        if d_dot_f <= 1e-6 {
+            return Some(ray);
+        }
+
+        let focus_point = ray.origin + (ray.direction * (lens.focus_distance / d_dot_f));
+
+        let (u, v) = lens.aperture.sample(lens_sample.0, lens_sample.1);
+        let origin = ray.origin + (((right * u) + (up * v)) * lens.aperture_radius);
+
+        Some(Ray {
+            origin,
+            direction: (focus_point - origin).normalize(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn look_at() -> LookAt {
+        LookAt {
+            eye: Float3::new(1.0, 2.0, 3.0),
+            target: Float3::new(3.0, 1.0, -2.0),
+            up: default_up(),
+            fov: 60.0,
+            aspect_ratio: None,
+        }
+    }
+
+    fn assert_close(a: Float3, b: Float3) {
+        assert!((a - b).norm() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    fn angle(a: Float3, b: Float3) -> f64 {
+        a.normalize().dot(&b.normalize()).clamp(-1.0, 1.0).acos().to_degrees()
+    }
+
+    #[test]
+    fn center_looks_at_the_target() {
+        let look_at = look_at();
+        let camera = Camera::from_look_at(&look_at, 1.5);
+        let forward = (look_at.target - look_at.eye).normalize();
+
+        let ray = camera.ray(0.0, 0.0, (0.5, 0.5)).unwrap();
+        assert_close(ray.origin, look_at.eye);
+        assert_close(ray.direction, forward);
+
+        // The top & bottom edges are half the field of view away, and up is
+        // still up.
+        let top = camera.ray(0.0, 1.0, (0.5, 0.5)).unwrap().direction;
+        let bottom = camera.ray(0.0, -1.0, (0.5, 0.5)).unwrap().direction;
+        assert!((angle(top, forward) - 30.0).abs() < 1e-9);
+        assert!((angle(bottom, forward) - 30.0).abs() < 1e-9);
+        assert!(top.y > bottom.y);
+
+        // The sides are wider by the aspect ratio, with x = 1 on the right.
+        let right = camera.ray(1.0, 0.0, (0.5, 0.5)).unwrap().direction;
+        let half_width = (30.0f64.to_radians().tan() * 1.5).atan().to_degrees();
+        assert!((angle(right, forward) - half_width).abs() < 1e-9);
+        assert!(right.dot(&forward.cross(&default_up())) > 0.0);
+    }
+
+    #[test]
+    fn closed_lens_is_a_pinhole() {
+        let pinhole = Camera::from_look_at(&look_at(), 1.5);
+        let camera = Camera {
+            lens: Some(Lens {
+                aperture_radius: 0.0,
+                focus_distance: 4.0,
+                aperture: Aperture::Disk,
+            }),
+            ..pinhole
+        };
+
+        for &(x, y) in &[(0.0, 0.0), (0.3, -0.7), (-1.0, 1.0)] {
+            for &lens_sample in &[(0.1, 0.9), (0.5, 0.5), (0.99, 0.0)] {
+                let expected = pinhole.ray(x, y, lens_sample).unwrap();
+                let ray = camera.ray(x, y, lens_sample).unwrap();
+
+                assert_close(ray.origin, expected.origin);
+                assert_close(ray.direction, expected.direction);
+            }
+        }
+    }
+
+    #[test]
+    fn open_lens_rays_meet_on_the_plane_of_focus() {
+        let pinhole = Camera::from_look_at(&look_at(), 1.5);
+        let forward = (pinhole.viewport_origin - pinhole.eye).normalize();
+        let camera = Camera {
+            lens: Some(Lens {
+                aperture_radius: 0.25,
+                focus_distance: 4.0,
+                aperture: Aperture::Disk,
+            }),
+            ..pinhole
+        };
+
+        let center = pinhole.ray(0.3, -0.7, (0.5, 0.5)).unwrap();
+        let focus_point = center.origin + (center.direction * (4.0 / center.direction.dot(&forward)));
+
+        for &lens_sample in &[(0.1, 0.9), (0.7, 0.2), (0.99, 0.0)] {
+            let ray = camera.ray(0.3, -0.7, lens_sample).unwrap();
+            let distance = (focus_point - ray.origin).dot(&forward) / ray.direction.dot(&forward);
+
+            assert!((ray.origin - pinhole.eye).norm() <= 0.25 + 1e-9);
+            assert_close(ray.origin + (ray.direction * distance), focus_point);
+        }
+    }
+
+    fn with_projection(projection: Projection, aspect_ratio: f64) -> Camera {
+        Camera {
+            projection,
+            ..Camera::from_look_at(&look_at(), aspect_ratio)
+        }
+    }
+
+    // Forward, right & up.
+    fn axes(camera: &Camera) -> (Float3, Float3, Float3) {
+        (
+            (camera.viewport_origin - camera.eye).normalize(),
+            camera.viewport_x_axis.normalize(),
+            camera.viewport_y_axis.normalize(),
+        )
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let camera = with_projection(Projection::Orthographic { height: 4.0 }, 1.5);
+        let (forward, right, up) = axes(&camera);
+
+        for &(x, y) in &[(0.0, 0.0), (1.0, 1.0), (-0.4, 0.8), (-1.0, -1.0)] {
+            let ray = camera.ray(x, y, (0.5, 0.5)).unwrap();
+
+            assert_close(ray.direction, forward);
+            assert_close(ray.origin, camera.eye + (right * (x * 3.0)) + (up * (y * 2.0)));
+        }
+    }
+
+    #[test]
+    fn fisheye_edges_are_half_the_fov_out() {
+        let camera = with_projection(Projection::Fisheye { fov: 180.0 }, 1.0);
+        let (forward, right, up) = axes(&camera);
+
+        assert_close(camera.ray(0.0, 0.0, (0.5, 0.5)).unwrap().direction, forward);
+        assert_close(camera.ray(1.0, 0.0, (0.5, 0.5)).unwrap().direction, right);
+        assert_close(camera.ray(0.0, -1.0, (0.5, 0.5)).unwrap().direction, -up);
+
+        // The angle grows linearly out to the image circle, past it there's
+        // nothing.
+        let halfway = camera.ray(-0.5, 0.0, (0.5, 0.5)).unwrap().direction;
+        assert!((angle(halfway, forward) - 45.0).abs() < 1e-9);
+        assert!(camera.ray(0.8, 0.8, (0.5, 0.5)).is_none());
+
+        // On a wide image the circle fits the height.
+        let wide = with_projection(Projection::Fisheye { fov: 300.0 }, 2.0);
+        assert!((angle(wide.ray(0.5, 0.0, (0.5, 0.5)).unwrap().direction, forward) - 150.0).abs() < 1e-9);
+        assert!((angle(wide.ray(0.0, 1.0, (0.5, 0.5)).unwrap().direction, forward) - 150.0).abs() < 1e-9);
+        assert!(wide.ray(0.6, 0.0, (0.5, 0.5)).is_none());
+    }
+
+    #[test]
+    fn equirectangular_covers_the_sphere() {
+        let camera = with_projection(Projection::Equirectangular, 2.0);
+        let (forward, right, up) = axes(&camera);
+        let direction = |x, y| camera.ray(x, y, (0.5, 0.5)).unwrap().direction;
+
+        assert_close(direction(0.0, 0.0), forward);
+        assert_close(direction(0.5, 0.0), right);
+        assert_close(direction(-0.5, 0.0), -right);
+        assert_close(direction(1.0, 0.0), -forward);
+        assert_close(direction(-1.0, 0.0), -forward);
+        assert_close(direction(0.3, 1.0), up);
+        assert_close(direction(-0.8, -1.0), -up);
+
+        // Latitude is linear in y.
+        assert!((angle(direction(0.0, 0.5), forward) - 45.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,409 @@
+use std::str::FromStr;
+
+use raytracer::filter::FilterType;
+use raytracer::image::{ExrPixelType, ImageFormat};
+use raytracer::render::{Integrator, RenderSettings};
+use raytracer::sampler::SamplerType;
+use raytracer::scene::AntiAliasType;
+use raytracer::stereo::{Stereo, StereoLayout};
+use raytracer::tiles::TileOrder;
+use raytracer::Scene;
+
+pub const USAGE: &str = "\
+Usage: raytracer [OPTIONS] <scene.json>
+       raytracer lint [--fix] [-o <file>] <scene.json>
+
+Renders a scene in a window, or headless straight to an image file.
+
+Options:
+  -o, --output <file>     Write the final image to <file>. The format is picked
+                          from the extension: png, ppm, pfm, raw, f32, exr, hdr
+      --headless          Render without opening a window (requires --output).
+                          This is the default when --output is given
+      --window            Open a window even when --output is given
+      --width <pixels>    Override the scene width
+      --height <pixels>   Override the scene height
+      --aa-type <type>    Override the anti-aliasing type: none, super-sample,
+                          monte-carlo, adaptive
+      --aa-rate <n>       Override the anti-aliasing rate (1-255)
+      --aa-threshold <t>  Override how different adaptive anti-aliasing samples
+                          have to be before the pixel is split
+      --sampler <s>       Override where sample points come from: random,
+                          stratified, halton, sobol, blue-noise
+      --seed <n>          Override the random seed, renders with the same seed
+                          are identical
+      --filter <f>        Override the pixel filter: box, tent, gaussian,
+                          mitchell, lanczos
+      --filter-radius <r> Override the pixel filter's radius in pixels
+      --shadow-samples <n>
+                          Override the number of shadow rays per light, lights
+                          that set their own keep it
+      --integrator <i>    Override how light is worked out: whitted,
+                          path-tracer
+      --max-depth <n>     Maximum ray depth (default 10)
+      --threads <n>       Number of render threads (default: one per core)
+      --tile-size <n>     Width and height of a render tile in pixels
+                          (default 32)
+      --tile-order <o>    Order tiles are rendered in: scanline, spiral,
+                          hilbert (default spiral)
+      --exr-float         Write OpenEXR files with 32-bit floats instead of half
+      --stereo <layout>   Render a left & right eye pair, written as: separate
+                          (<output>-left & <output>-right), side-by-side,
+                          over-under, anaglyph. Headless only
+      --interocular <d>   Distance between the eyes in scene units
+      --convergence <d>   Distance to where the eyes' views line up
+  -h, --help              Print this message
+
+Lint checks a scene for mistakes like non-unit normals, triangles that don't
+match their vertices or shapes that don't enclose anything.
+
+Lint options:
+      --fix               Repair everything that can be derived from the rest
+                          of the file and write the scene back out
+  -o, --output <file>     Write the repaired scene to <file> instead of over
+                          the original
+";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mode {
+    Window,
+    Headless,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub scene: String,
+    pub output: Option<(String, ImageFormat)>,
+    pub mode: Mode,
+
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub aa_type: Option<AntiAliasType>,
+    pub aa_rate: Option<u8>,
+    pub aa_threshold: Option<f64>,
+    pub sampler: Option<SamplerType>,
+    pub seed: Option<u64>,
+    pub filter: Option<FilterType>,
+    pub filter_radius: Option<f64>,
+    pub shadow_samples: Option<u32>,
+    pub integrator: Option<Integrator>,
+    pub max_depth: u32,
+    pub threads: Option<usize>,
+    pub tile_size: Option<u32>,
+    pub tile_order: Option<TileOrder>,
+
+    pub stereo_layout: Option<StereoLayout>,
+    pub interocular_distance: Option<f64>,
+    pub convergence_distance: Option<f64>,
+}
+
+#[derive(Debug)]
+pub struct LintOptions {
+    pub scene: String,
+    pub fix: bool,
+    pub output: Option<String>,
+}
+
+pub enum Command {
+    Render(Options),
+    Lint(LintOptions),
+    Help,
+}
+
+impl Options {
+    pub fn apply_overrides(&self, scene: &mut Scene) -> Result<(), String> {
+        scene.set_resolution(self.width.unwrap_or(scene.width), self.height.unwrap_or(scene.height));
+
+        if let Some(aa_type) = self.aa_type {
+            scene.aa_type = aa_type;
+        }
+
+        if let Some(aa_rate) = self.aa_rate {
+            scene.aa_rate = aa_rate;
+        }
+
+        if let Some(aa_threshold) = self.aa_threshold {
+            scene.aa_threshold = aa_threshold;
+        }
+
+        if let Some(sampler) = self.sampler {
+            scene.sampler = sampler;
+        }
+
+        if let Some(seed) = self.seed {
+            scene.seed = seed;
+        }
+
+        if let Some(filter) = self.filter {
+            // The scene's radius was picked for its own filter.
+            if filter != scene.filter {
+                scene.filter_radius = None;
+            }
+
+            scene.filter = filter;
+        }
+
+        if let Some(filter_radius) = self.filter_radius {
+            scene.filter_radius = Some(filter_radius);
+        }
+
+        if let Some(shadow_samples) = self.shadow_samples {
+            scene.shadow_samples = shadow_samples;
+        }
+
+        if let Some(integrator) = self.integrator {
+            scene.integrator = integrator;
+        }
+
+        let stereo_flags = self.stereo_layout.is_some()
+            || self.interocular_distance.is_some()
+            || self.convergence_distance.is_some();
+
+        if stereo_flags {
+            let stereo = match (scene.stereo, self.interocular_distance, self.convergence_distance) {
+                (Some(stereo), _, _) => stereo,
+                (None, Some(interocular_distance), Some(convergence_distance)) => Stereo {
+                    interocular_distance,
+                    convergence_distance,
+                    layout: StereoLayout::default(),
+                },
+                _ => {
+                    return Err(
+                        "the scene has no stereo settings, --interocular and --convergence are both needed".to_string(),
+                    )
+                }
+            };
+
+            scene.stereo = Some(Stereo {
+                interocular_distance: self.interocular_distance.unwrap_or(stereo.interocular_distance),
+                convergence_distance: self.convergence_distance.unwrap_or(stereo.convergence_distance),
+                layout: self.stereo_layout.unwrap_or(stereo.layout),
+            });
+        }
+
+        if scene.stereo.is_some() && self.mode == Mode::Window {
+            return Err("stereo rendering is headless only, use --output".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn render_settings(&self) -> RenderSettings {
+        let mut settings = RenderSettings {
+            max_depth: self.max_depth,
+            ..Default::default()
+        };
+
+        if let Some(threads) = self.threads {
+            settings.threads = threads;
+        }
+
+        if let Some(tile_size) = self.tile_size {
+            settings.tile_size = tile_size;
+        }
+
+        if let Some(tile_order) = self.tile_order {
+            settings.tile_order = tile_order;
+        }
+
+        settings
+    }
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} requires a value", flag))?;
+
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for {}", value, flag))
+}
+
+fn parse_positive<T: FromStr + PartialOrd + Default>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value: T = parse_value(flag, value)?;
+
+    if value <= T::default() {
+        return Err(format!("{} must be greater than zero", flag));
+    }
+
+    Ok(value)
+}
+
+// The float ones get the same checks the scene file does, as overrides are
+// applied after it's validated.
+fn parse_finite(flag: &str, value: Option<String>) -> Result<f64, String> {
+    let value: f64 = parse_value(flag, value)?;
+
+    if !value.is_finite() {
+        return Err(format!("{} must be a finite number", flag));
+    }
+
+    Ok(value)
+}
+
+fn parse_positive_finite(flag: &str, value: Option<String>) -> Result<f64, String> {
+    let value = parse_finite(flag, value)?;
+
+    if value <= 0.0 {
+        return Err(format!("{} must be greater than zero", flag));
+    }
+
+    Ok(value)
+}
+
+fn parse_non_negative(flag: &str, value: Option<String>) -> Result<f64, String> {
+    let value = parse_finite(flag, value)?;
+
+    if value < 0.0 {
+        return Err(format!("{} can't be negative", flag));
+    }
+
+    Ok(value)
+}
+
+fn parse_lint<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut scene = None;
+    let mut fix = false;
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--fix" => fix = true,
+            "-o" | "--output" => output = Some(parse_value::<String>(&arg, args.next())?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => {
+                if scene.is_some() {
+                    return Err(format!("unexpected argument '{}'", arg));
+                }
+
+                scene = Some(arg);
+            }
+        }
+    }
+
+    let scene = scene.ok_or_else(|| "you must provide a scene file".to_string())?;
+
+    if output.is_some() && !fix {
+        return Err("--output requires --fix".to_string());
+    }
+
+    Ok(Command::Lint(LintOptions { scene, fix, output }))
+}
+
+pub fn parse<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut args = args.peekable();
+
+    if args.peek().map(String::as_str) == Some("lint") {
+        args.next();
+        return parse_lint(args);
+    }
+
+    let mut scene = None;
+    let mut output = None;
+    let mut mode = None;
+    let mut exr_float = false;
+
+    let mut width = None;
+    let mut height = None;
+    let mut aa_type = None;
+    let mut aa_rate = None;
+    let mut aa_threshold = None;
+    let mut sampler = None;
+    let mut seed = None;
+    let mut filter = None;
+    let mut filter_radius = None;
+    let mut shadow_samples = None;
+    let mut integrator = None;
+    let mut max_depth = 10;
+    let mut threads = None;
+    let mut tile_size = None;
+    let mut tile_order = None;
+    let mut stereo_layout = None;
+    let mut interocular_distance = None;
+    let mut convergence_distance = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-o" | "--output" => output = Some(parse_value::<String>(&arg, args.next())?),
+            "--headless" => mode = Some(Mode::Headless),
+            "--window" => mode = Some(Mode::Window),
+            "--width" => width = Some(parse_positive(&arg, args.next())?),
+            "--height" => height = Some(parse_positive(&arg, args.next())?),
+            "--aa-type" => aa_type = Some(parse_value(&arg, args.next())?),
+            "--aa-rate" => aa_rate = Some(parse_positive(&arg, args.next())?),
+            "--aa-threshold" => aa_threshold = Some(parse_non_negative(&arg, args.next())?),
+            "--sampler" => sampler = Some(parse_value(&arg, args.next())?),
+            "--seed" => seed = Some(parse_value(&arg, args.next())?),
+            "--filter" => filter = Some(parse_value(&arg, args.next())?),
+            "--filter-radius" => filter_radius = Some(parse_positive_finite(&arg, args.next())?),
+            "--shadow-samples" => shadow_samples = Some(parse_positive(&arg, args.next())?),
+            "--integrator" => integrator = Some(parse_value(&arg, args.next())?),
+            "--max-depth" => max_depth = parse_positive(&arg, args.next())?,
+            "--threads" => threads = Some(parse_positive(&arg, args.next())?),
+            "--tile-size" => tile_size = Some(parse_positive(&arg, args.next())?),
+            "--tile-order" => tile_order = Some(parse_value(&arg, args.next())?),
+            "--exr-float" => exr_float = true,
+            "--stereo" => stereo_layout = Some(parse_value(&arg, args.next())?),
+            "--interocular" => interocular_distance = Some(parse_positive_finite(&arg, args.next())?),
+            "--convergence" => convergence_distance = Some(parse_positive_finite(&arg, args.next())?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => {
+                if scene.is_some() {
+                    return Err(format!("unexpected argument '{}'", arg));
+                }
+
+                scene = Some(arg);
+            }
+        }
+    }
+
+    let scene = scene.ok_or_else(|| "you must provide a scene file".to_string())?;
+
+    let output = match output {
+        Some(filename) => {
+            let format = match ImageFormat::from_filename(&filename) {
+                Some(ImageFormat::Exr(_)) if exr_float => ImageFormat::Exr(ExrPixelType::Float),
+                Some(format) => format,
+                None => return Err(format!("unknown image format for '{}'", filename)),
+            };
+
+            Some((filename, format))
+        }
+        None => None,
+    };
+
+    let mode = match mode {
+        Some(mode) => mode,
+        None if output.is_some() => Mode::Headless,
+        None => Mode::Window,
+    };
+
+    if mode == Mode::Headless && output.is_none() {
+        return Err("--headless requires --output".to_string());
+    }
+
+    Ok(Command::Render(Options {
+        scene,
+        output,
+        mode,
+        width,
+        height,
+        aa_type,
+        aa_rate,
+        aa_threshold,
+        sampler,
+        seed,
+        filter,
+        filter_radius,
+        shadow_samples,
+        integrator,
+        max_depth,
+        threads,
+        tile_size,
+        tile_order,
+        stereo_layout,
+        interocular_distance,
+        convergence_distance,
+    }))
+}
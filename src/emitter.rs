@@ -0,0 +1,350 @@
+use std::f64::consts::PI;
+
+use super::sampler::uniform_cone;
+use super::shapes::*;
+use super::Float3;
+
+/// A flat piece of an emitter's surface, a triangle or a parallelogram.
+#[derive(Debug, Copy, Clone)]
+struct Patch {
+    origin: Float3,
+    edges: [Float3; 2],
+    normal: Float3,
+    area: f64,
+    triangle: bool,
+}
+
+impl Patch {
+    fn new(origin: Float3, edges: [Float3; 2], normal: Float3, triangle: bool) -> Self {
+        let area = edges[0].cross(&edges[1]).norm() * if triangle { 0.5 } else { 1.0 };
+
+        Patch {
+            origin,
+            edges,
+            normal,
+            area,
+            triangle,
+        }
+    }
+
+    // Evenly spread over the patch's area.
+    fn point(&self, u: f64, v: f64) -> Float3 {
+        if self.triangle {
+            let su = u.sqrt();
+
+            self.origin + (self.edges[0] * (su * (1.0 - v))) + (self.edges[1] * (su * v))
+        } else {
+            self.origin + (self.edges[0] * u) + (self.edges[1] * v)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Surface {
+    Sphere { center: Float3, radius: f64 },
+    /// `two_sided` patches give off light from both sides, otherwise only
+    /// the side their normal is on.
+    Patches { patches: Vec<Patch>, two_sided: bool },
+}
+
+/// A point picked on an emitter as seen from a point being lit.
+#[derive(Debug, Copy, Clone)]
+pub struct EmitterSample {
+    pub point: Float3,
+    /// Radiance leaving `point` towards the lit point.
+    pub radiance: Float3,
+    /// Probability density of picking the direction towards `point`, per
+    /// steradian.
+    pub pdf: f64,
+}
+
+/// A shape with an emissive material, set up so points can be picked on the
+/// parts of it that can be seen.
+#[derive(Debug, Clone)]
+pub struct Emitter {
+    /// Index of the shape in `Scene::shapes`.
+    pub shape: usize,
+    pub radiance: Float3,
+    surface: Surface,
+}
+
+// The four corners of a rhombohedron that lie on `plane` as a parallelogram.
+fn face(corners: &[Float3], plane: &Plane) -> Option<Patch> {
+    let on_plane: Vec<Float3> = corners
+        .iter()
+        .filter(|corner| ((*corner - plane.point).dot(&plane.normal)).abs() < 1e-6)
+        .copied()
+        .collect();
+
+    if on_plane.len() != 4 {
+        return None;
+    }
+
+    // The corner furthest from the first is opposite it, the other two are
+    // along its edges.
+    let origin = on_plane[0];
+    let opposite = (1..4)
+        .max_by(|a, b| {
+            let a = (on_plane[*a] - origin).norm();
+            let b = (on_plane[*b] - origin).norm();
+            a.partial_cmp(&b).unwrap()
+        })
+        .unwrap();
+
+    let mut edges = (1..4).filter(|i| *i != opposite).map(|i| on_plane[i] - origin);
+
+    Some(Patch::new(
+        origin,
+        [edges.next().unwrap(), edges.next().unwrap()],
+        plane.normal,
+        false,
+    ))
+}
+
+impl Emitter {
+    /// `None` if the shape doesn't give off light or can't be sampled.
+    /// Ellipsoids are only seen by rays that hit them.
+    pub fn from_shape(index: usize, shape: &Primitive) -> Option<Self> {
+        let material = shape.material();
+
+        if !material.is_emissive() {
+            return None;
+        }
+
+        let surface = match shape {
+            Primitive::Sphere(sphere) => Surface::Sphere {
+                center: sphere.center,
+                radius: sphere.radius,
+            },
+            Primitive::Polygon(polygon) => Surface::Patches {
+                patches: polygon
+                    .triangles
+                    .iter()
+                    .map(|triangle| Patch::new(triangle.vertices[0], triangle.edges, triangle.normal, true))
+                    .collect(),
+                two_sided: true,
+            },
+            Primitive::Rhombohedron(rhombohedron) => {
+                let corners = rhombohedron.corners();
+
+                Surface::Patches {
+                    patches: rhombohedron
+                        .planes
+                        .iter()
+                        .filter_map(|plane| face(&corners, plane))
+                        .collect(),
+                    two_sided: false,
+                }
+            }
+            Primitive::Ellipsoid(_) => return None,
+        };
+
+        if let Surface::Patches { ref patches, .. } = surface {
+            if patches.is_empty() {
+                return None;
+            }
+        }
+
+        Some(Emitter {
+            shape: index,
+            radiance: material.emission,
+            surface,
+        })
+    }
+
+    /// Picks a point on the emitter that might be seen from `position` using
+    /// `u` & `v` in [0, 1). `None` if the part picked faces away or
+    /// `position` is inside the emitter.
+    pub fn sample(&self, position: &Float3, u: f64, v: f64) -> Option<EmitterSample> {
+        match self.surface {
+            Surface::Sphere { center, radius } => {
+                // Only the cone of directions that hit the sphere.
+                let to_center = center - position;
+                let distance = to_center.norm();
+
+                if distance <= radius {
+                    return None;
+                }
+
+                let w = to_center / distance;
+                let sin_theta_max = radius / distance;
+                let cos_theta_max = (1.0 - (sin_theta_max * sin_theta_max)).sqrt();
+
+                let direction = uniform_cone(&w, cos_theta_max, u, v);
+
+                // Where it meets the near side of the sphere.
+                let cos_theta = direction.dot(&w);
+                let sin_theta_2 = f64::max(0.0, 1.0 - (cos_theta * cos_theta));
+                let half_chord = f64::max(0.0, (radius * radius) - (distance * distance * sin_theta_2)).sqrt();
+
+                Some(EmitterSample {
+                    point: position + (direction * ((distance * cos_theta) - half_chord)),
+                    radiance: self.radiance,
+                    pdf: 1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+                })
+            }
+            Surface::Patches { ref patches, two_sided } => {
+                let facing = |patch: &&Patch| two_sided || (position - patch.origin).dot(&patch.normal) > 0.0;
+
+                let total_area: f64 = patches.iter().filter(facing).map(|patch| patch.area).sum();
+
+                if total_area <= 0.0 {
+                    return None;
+                }
+
+                // Pick a patch by its area and reuse what's left of `u` for
+                // the point on it.
+                let mut target = u * total_area;
+                let mut chosen = None;
+
+                for patch in patches.iter().filter(facing) {
+                    chosen = Some((patch, (target / patch.area).min(1.0)));
+
+                    if target < patch.area {
+                        break;
+                    }
+
+                    target -= patch.area;
+                }
+
+                let (patch, u) = chosen?;
+                let point = patch.point(u, v);
+
+                let to_point = point - position;
+                let distance_2 = to_point.dot(&to_point);
+                let cos_light = (to_point.dot(&patch.normal) / distance_2.sqrt()).abs();
+
+                if cos_light < 1e-9 {
+                    return None;
+                }
+
+                // Uniform over the area, as a density over solid angle.
+                Some(EmitterSample {
+                    point,
+                    radiance: self.radiance,
+                    pdf: distance_2 / (cos_light * total_area),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRID: usize = 300;
+
+    fn glowing() -> Material {
+        Material {
+            diffuse: Float3::new(0.0, 0.0, 0.0),
+            specular_coefficient: 0.0,
+            specular_power: 1.0,
+            attenuation: Float3::new(1.0, 1.0, 1.0),
+            electric_permittivity: 1.0,
+            magnetic_permeability: 1.0,
+            index_of_refraction: 1.0,
+            emission: Float3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    // The middle of every cell of a grid over [0, 1)².
+    fn grid() -> impl Iterator<Item = (f64, f64)> {
+        (0..GRID * GRID).map(|i| {
+            (
+                ((i % GRID) as f64 + 0.5) / GRID as f64,
+                ((i / GRID) as f64 + 0.5) / GRID as f64,
+            )
+        })
+    }
+
+    // How much of the sphere of directions around `position` hits `shape`,
+    // by firing rays evenly in every direction.
+    fn solid_angle(shape: &Primitive, position: &Float3) -> f64 {
+        let forward = Float3::new(0.0, 0.0, 1.0);
+        let hits = grid()
+            .filter(|&(u, v)| {
+                let ray = Ray {
+                    origin: *position,
+                    direction: uniform_cone(&forward, -1.0, u, v),
+                };
+
+                shape.intersect(&ray, f64::MAX).is_some()
+            })
+            .count();
+
+        4.0 * PI * (hits as f64 / (GRID * GRID) as f64)
+    }
+
+    // The pdf integrates to 1 over the directions it picks from exactly when
+    // the mean of 1 / pdf over its own samples is their solid angle.
+    fn check_pdf(shape: &Primitive, position: Float3) {
+        let emitter = Emitter::from_shape(0, shape).unwrap();
+
+        let inverse_pdf: f64 = grid()
+            .filter_map(|(u, v)| emitter.sample(&position, u, v))
+            .map(|sample| 1.0 / sample.pdf)
+            .sum::<f64>()
+            / (GRID * GRID) as f64;
+
+        let solid_angle = solid_angle(shape, &position);
+
+        assert!(
+            (inverse_pdf / solid_angle - 1.0).abs() < 0.01,
+            "{:?}: {} != {}",
+            shape,
+            inverse_pdf,
+            solid_angle
+        );
+    }
+
+    #[test]
+    fn sphere_pdf_integrates_to_one() {
+        let sphere = Primitive::Sphere(Sphere {
+            center: Float3::new(0.5, -0.2, 0.0),
+            radius: 1.0,
+            material: glowing(),
+        });
+        let position = Float3::new(0.0, 0.0, 2.5);
+
+        // The sphere's pdf is the same everywhere in its cone.
+        let pdf = Emitter::from_shape(0, &sphere).unwrap().sample(&position, 0.3, 0.6).unwrap().pdf;
+        let solid_angle = solid_angle(&sphere, &position);
+        assert!((pdf * solid_angle - 1.0).abs() < 0.01, "{}", pdf * solid_angle);
+
+        check_pdf(&sphere, position);
+
+        // Points picked are on the side facing `position`.
+        let emitter = Emitter::from_shape(0, &sphere).unwrap();
+        let sample = emitter.sample(&position, 0.9, 0.1).unwrap();
+        assert!(((sample.point - Float3::new(0.5, -0.2, 0.0)).norm() - 1.0).abs() < 1e-9);
+        assert!((sample.point - Float3::new(0.5, -0.2, 0.0)).dot(&(position - sample.point)) > 0.0);
+
+        // There's nothing to pick from inside.
+        assert!(emitter.sample(&Float3::new(0.5, 0.0, 0.0), 0.5, 0.5).is_none());
+    }
+
+    #[test]
+    fn patch_pdfs_integrate_to_one() {
+        let quad = Polygon::from_vertices(
+            vec![
+                Float3::new(-1.0, -1.0, 0.0),
+                Float3::new(1.5, -1.0, 0.0),
+                Float3::new(1.0, 1.0, 0.0),
+                Float3::new(-1.0, 1.0, 0.0),
+            ],
+            glowing(),
+        );
+        check_pdf(&Primitive::Polygon(quad.clone()), Float3::new(0.3, 0.2, 1.5));
+        check_pdf(&Primitive::Polygon(quad), Float3::new(-0.4, 0.1, -1.0));
+
+        let cube = Rhombohedron::from_corner_and_edges(
+            Float3::new(-0.5, -0.5, -0.5),
+            Float3::new(1.0, 0.0, 0.0),
+            Float3::new(0.0, 1.0, 0.0),
+            Float3::new(0.0, 0.0, 1.0),
+            glowing(),
+        );
+        check_pdf(&Primitive::Rhombohedron(cube), Float3::new(1.2, 0.9, 1.6));
+    }
+}
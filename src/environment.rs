@@ -0,0 +1,407 @@
+use serde::{Serialize, Deserialize};
+
+use std::f64::consts::PI;
+use std::path::Path;
+
+use super::image::ImageBuffer;
+use super::sampler::cosine_hemisphere;
+use super::shapes::Light;
+use super::sky::{Sky, SkyModel};
+use super::Float3;
+
+fn default_intensity() -> f64 {
+    1.0
+}
+
+fn is_default_intensity(intensity: &f64) -> bool {
+    *intensity == default_intensity()
+}
+
+/// What's seen in directions where there's nothing in the scene, it also
+/// lights the scene. In scene files this is the `environment` object.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Environment {
+    /// The same color in every direction.
+    Constant(Float3),
+    /// Blends from `bottom` straight down to `top` straight up.
+    Gradient { top: Float3, bottom: Float3 },
+    /// An equirectangular (latitude-longitude) .hdr or .pfm image, the top
+    /// row is straight up and the middle of the image is towards -z. `path`
+    /// is relative to the scene file. `rotation` turns it around the y axis,
+    /// in degrees.
+    Image {
+        path: String,
+        #[serde(default = "default_intensity", skip_serializing_if = "is_default_intensity")]
+        intensity: f64,
+        #[serde(default, skip_serializing_if = "crate::is_default")]
+        rotation: f64,
+    },
+    /// A daylight sky, which also adds a directional light for the sun.
+    Sky(Sky),
+}
+
+impl Environment {
+    /// Describes what's wrong if this can't be rendered, along with the field
+    /// at fault.
+    pub fn problem(&self) -> Option<(&'static str, String)> {
+        match *self {
+            Environment::Image { intensity, .. } if intensity < 0.0 || !intensity.is_finite() => {
+                Some(("intensity", format!("can't be negative, found {}", intensity)))
+            }
+            Environment::Image { rotation, .. } if !rotation.is_finite() => {
+                Some(("rotation", format!("must be a number, found {}", rotation)))
+            }
+            Environment::Sky(ref sky) => sky.problem(),
+            _ => None,
+        }
+    }
+}
+
+/// A piecewise constant distribution over `0..weights.len()`.
+#[derive(Debug, Clone)]
+struct Distribution1d {
+    weights: Vec<f64>,
+    /// `cdf[i]` is the chance of picking something before `i`.
+    cdf: Vec<f64>,
+    total: f64,
+}
+
+impl Distribution1d {
+    fn new(weights: Vec<f64>) -> Self {
+        let total: f64 = weights.iter().sum();
+
+        let mut cdf = Vec::with_capacity(weights.len() + 1);
+        let mut sum = 0.0;
+        cdf.push(0.0);
+
+        for weight in weights.iter() {
+            sum += weight;
+            cdf.push(if total > 0.0 { sum / total } else { 0.0 });
+        }
+
+        Distribution1d { weights, cdf, total }
+    }
+
+    // The index picked by `u` in [0, 1) and how far through its step `u` was.
+    fn sample(&self, u: f64) -> (usize, f64) {
+        // The first entry whose step ends past `u`.
+        let index = self.cdf[1..].partition_point(|end| *end <= u).min(self.weights.len() - 1);
+
+        let start = self.cdf[index];
+        let width = self.cdf[index + 1] - start;
+        let offset = if width > 0.0 { ((u - start) / width).clamp(0.0, 1.0) } else { 0.0 };
+
+        (index, offset)
+    }
+
+    // Density over [0, 1) of picking a point in step `index`.
+    fn pdf(&self, index: usize) -> f64 {
+        if self.total <= 0.0 {
+            return 0.0;
+        }
+
+        self.weights[index] * (self.weights.len() as f64) / self.total
+    }
+}
+
+/// Picks pixels of an image in proportion to their brightness, rows first
+/// then the column within the row.
+#[derive(Debug, Clone)]
+struct Distribution2d {
+    rows: Vec<Distribution1d>,
+    marginal: Distribution1d,
+}
+
+impl Distribution2d {
+    // `weights` is row major.
+    fn new(width: usize, height: usize, weights: &[f64]) -> Self {
+        let rows: Vec<Distribution1d> = weights
+            .chunks(width)
+            .take(height)
+            .map(|row| Distribution1d::new(row.to_vec()))
+            .collect();
+
+        let marginal = Distribution1d::new(rows.iter().map(|row| row.total).collect());
+
+        Distribution2d { rows, marginal }
+    }
+
+    // A point in [0, 1)², rows going down `v`, and its density.
+    fn sample(&self, u: f64, v: f64) -> ((f64, f64), f64) {
+        let (row, row_offset) = self.marginal.sample(v);
+        let (column, column_offset) = self.rows[row].sample(u);
+
+        let width = self.rows[row].weights.len() as f64;
+        let height = self.rows.len() as f64;
+
+        (
+            (((column as f64) + column_offset) / width, ((row as f64) + row_offset) / height),
+            self.marginal.pdf(row) * self.rows[row].pdf(column),
+        )
+    }
+}
+
+// Longitude from -z turning towards +x and latitude from straight up, both
+// scaled to [0, 1).
+fn direction_to_uv(direction: &Float3, rotation: f64) -> (f64, f64) {
+    let phi = direction.x.atan2(-direction.z) - rotation.to_radians();
+    let theta = direction.y.clamp(-1.0, 1.0).acos();
+
+    ((0.5 + (phi / (2.0 * PI))).rem_euclid(1.0), theta / PI)
+}
+
+fn uv_to_direction(u: f64, v: f64, rotation: f64) -> Float3 {
+    let phi = ((u - 0.5) * 2.0 * PI) + rotation.to_radians();
+    let theta = v * PI;
+
+    Float3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+}
+
+fn luminance(color: &Float3) -> f64 {
+    (0.2126 * color.x) + (0.7152 * color.y) + (0.0722 * color.z)
+}
+
+/// A direction picked to light a point from the environment.
+#[derive(Debug, Copy, Clone)]
+pub struct EnvironmentSample {
+    pub direction: Float3,
+    pub radiance: Float3,
+    /// Probability density of picking `direction`, per steradian.
+    pub pdf: f64,
+}
+
+/// An `Environment` ready to render, with its image loaded.
+#[derive(Debug, Clone)]
+pub struct Background {
+    pub environment: Environment,
+    image: Option<ImageBuffer>,
+    /// Only for images, other environments are smooth enough that cosine
+    /// weighted directions do well.
+    distribution: Option<Distribution2d>,
+    sky: Option<SkyModel>,
+    sun: Option<Light>,
+}
+
+impl Background {
+    /// Loads the image of an `Environment::Image`, `directory` is where the
+    /// scene file is.
+    pub fn new(environment: &Environment, directory: &Path) -> Result<Self, String> {
+        let (image, distribution) = match *environment {
+            Environment::Image { ref path, .. } => {
+                let full_path = directory.join(path);
+                let image = ImageBuffer::from_file(&full_path.to_string_lossy())
+                    .map_err(|err| format!("can't load '{}': {}", full_path.display(), err))?;
+
+                if image.width == 0 || image.height == 0 {
+                    return Err(format!("'{}' is empty", full_path.display()));
+                }
+
+                // Rows are weighted by how much of the sphere they cover.
+                let (width, height) = (image.width as usize, image.height as usize);
+                let mut weights = Vec::with_capacity(width * height);
+
+                for row in 0..height {
+                    let sin_theta = (PI * ((row as f64) + 0.5) / (height as f64)).sin();
+
+                    for x in 0..width {
+                        let y = (height - 1 - row) as u32;
+                        weights.push(f64::max(0.0, luminance(&image.get_pixel(x as u32, y))) * sin_theta);
+                    }
+                }
+
+                let distribution = Distribution2d::new(width, height, &weights);
+                let distribution = if distribution.marginal.total > 0.0 { Some(distribution) } else { None };
+
+                (Some(image), distribution)
+            }
+            _ => (None, None),
+        };
+
+        let sky = match *environment {
+            Environment::Sky(ref sky) => Some(SkyModel::new(sky)),
+            _ => None,
+        };
+
+        Ok(Background {
+            environment: environment.clone(),
+            image,
+            distribution,
+            sun: sky.as_ref().and_then(|sky| sky.sun()),
+            sky,
+        })
+    }
+
+    /// The light that comes with the environment, only the sun of a sky.
+    pub fn sun(&self) -> Option<&Light> {
+        self.sun.as_ref()
+    }
+
+    /// Light arriving from `direction` (a unit vector).
+    pub fn radiance(&self, direction: &Float3) -> Float3 {
+        match self.environment {
+            Environment::Constant(color) => color,
+            Environment::Gradient { top, bottom } => {
+                let t = 0.5 * (direction.y + 1.0);
+
+                (bottom * (1.0 - t)) + (top * t)
+            }
+            Environment::Image { intensity, rotation, .. } => {
+                let image = self.image.as_ref().unwrap();
+                let (u, v) = direction_to_uv(direction, rotation);
+
+                let x = ((u * image.width as f64) as u32).min(image.width - 1);
+                let row = ((v * image.height as f64) as u32).min(image.height - 1);
+
+                image.get_pixel(x, image.height - 1 - row) * intensity
+            }
+            Environment::Sky(_) => self.sky.as_ref().unwrap().radiance(direction),
+        }
+    }
+
+    /// Picks a direction to light a point with surface normal `normal` from,
+    /// using `u` & `v` in [0, 1). Images favor their bright parts, otherwise
+    /// directions are cosine weighted around the normal.
+    pub fn sample(&self, normal: &Float3, u: f64, v: f64) -> Option<EnvironmentSample> {
+        let (direction, pdf) = match (&self.distribution, &self.environment) {
+            (Some(distribution), Environment::Image { rotation, .. }) => {
+                let ((u, v), pdf_uv) = distribution.sample(u, v);
+                let sin_theta = (v * PI).sin();
+
+                if pdf_uv <= 0.0 || sin_theta <= 0.0 {
+                    return None;
+                }
+
+                // From the image's area to the sphere's solid angle.
+                (uv_to_direction(u, v, *rotation), pdf_uv / (2.0 * PI * PI * sin_theta))
+            }
+            _ => {
+                let direction = cosine_hemisphere(normal, u, v);
+                let cos_theta = direction.dot(normal);
+
+                if cos_theta <= 0.0 {
+                    return None;
+                }
+
+                (direction, cos_theta / PI)
+            }
+        };
+
+        Some(EnvironmentSample {
+            direction,
+            radiance: self.radiance(&direction),
+            pdf,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `count` evenly spread values in [0, 1).
+    fn stratified(count: usize) -> impl Iterator<Item = f64> {
+        (0..count).map(move |i| ((i as f64) + 0.5) / (count as f64))
+    }
+
+    #[test]
+    fn distribution_1d_picks_in_proportion() {
+        let distribution = Distribution1d::new(vec![1.0, 0.0, 3.0, 4.0]);
+
+        assert_eq!(distribution.cdf, vec![0.0, 0.125, 0.125, 0.5, 1.0]);
+
+        let mut counts = [0usize; 4];
+        for u in stratified(8000) {
+            let (index, offset) = distribution.sample(u);
+            assert!((0.0..=1.0).contains(&offset));
+
+            // `u` is `offset` of the way through the step it picked.
+            let start = distribution.cdf[index];
+            let end = distribution.cdf[index + 1];
+            assert!((start + (offset * (end - start)) - u).abs() < 1e-12);
+
+            counts[index] += 1;
+        }
+
+        assert_eq!(counts, [1000, 0, 3000, 4000]);
+    }
+
+    #[test]
+    fn distribution_1d_pdf_integrates_to_one() {
+        let weights = vec![0.5, 2.0, 0.0, 1.5, 6.0];
+        let distribution = Distribution1d::new(weights.clone());
+
+        let integral: f64 = (0..weights.len()).map(|i| distribution.pdf(i) / (weights.len() as f64)).sum();
+        assert!((integral - 1.0).abs() < 1e-12);
+
+        assert_eq!(distribution.pdf(2), 0.0);
+        assert!((distribution.pdf(4) - (6.0 * 5.0 / 10.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn distribution_1d_without_weight() {
+        let distribution = Distribution1d::new(vec![0.0, 0.0, 0.0]);
+
+        assert_eq!(distribution.pdf(1), 0.0);
+
+        let (index, offset) = distribution.sample(0.5);
+        assert!(index < 3);
+        assert_eq!(offset, 0.0);
+    }
+
+    #[test]
+    fn distribution_2d_picks_pixels_in_proportion() {
+        let (width, height) = (3, 2);
+        let weights = [1.0, 2.0, 0.0, 0.0, 4.0, 1.0];
+        let total: f64 = weights.iter().sum();
+        let distribution = Distribution2d::new(width, height, &weights);
+
+        let mut counts = [0usize; 6];
+        let mut integral = 0.0;
+
+        for v in stratified(400) {
+            for u in stratified(400) {
+                let ((x, y), pdf) = distribution.sample(u, v);
+                assert!((0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y));
+
+                let column = ((x * width as f64) as usize).min(width - 1);
+                let row = ((y * height as f64) as usize).min(height - 1);
+                let pixel = (row * width) + column;
+
+                let expected_pdf = weights[pixel] * ((width * height) as f64) / total;
+                assert!((pdf - expected_pdf).abs() < 1e-12);
+
+                counts[pixel] += 1;
+                integral += 1.0 / pdf;
+            }
+        }
+
+        let samples = (400 * 400) as f64;
+        for (count, weight) in counts.iter().zip(weights.iter()) {
+            assert!(((*count as f64) / samples - (weight / total)).abs() < 1e-3);
+        }
+
+        // Every pixel with weight was reached, so this estimates the area
+        // of [0, 1)² they cover.
+        assert!((integral / samples - (4.0 / 6.0)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn uv_and_direction_round_trip() {
+        for rotation in [0.0, 30.0, -120.0].iter() {
+            for v in stratified(7) {
+                for u in stratified(9) {
+                    let direction = uv_to_direction(u, v, *rotation);
+                    assert!((direction.norm() - 1.0).abs() < 1e-12);
+
+                    let (u2, v2) = direction_to_uv(&direction, *rotation);
+                    assert!((u - u2).abs() < 1e-9, "u {} came back as {}", u, u2);
+                    assert!((v - v2).abs() < 1e-9, "v {} came back as {}", v, v2);
+                }
+            }
+        }
+
+        // The middle of the image is towards -z.
+        let direction = uv_to_direction(0.5, 0.5, 0.0);
+        assert!((direction - Float3::new(0.0, 0.0, -1.0)).norm() < 1e-12);
+    }
+}
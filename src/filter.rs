@@ -0,0 +1,212 @@
+use serde::{Serialize, Deserialize};
+
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+/// How the samples around a pixel are weighted to get its color.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum FilterType {
+    /// Every sample within the radius counts the same, with the default
+    /// radius this is the plain average of the pixel's own samples.
+    #[default]
+    Box,
+    /// Weights fall off linearly to 0 at the radius.
+    Tent,
+    /// Smooth, slightly blurry.
+    Gaussian,
+    /// Mitchell-Netravali with B = C = 1/3, sharper than Gaussian with little
+    /// ringing.
+    Mitchell,
+    /// Windowed sinc, the sharpest but rings around hard edges.
+    Lanczos,
+}
+
+impl FilterType {
+    /// Radius in pixels used when the scene doesn't give one.
+    pub fn default_radius(&self) -> f64 {
+        match *self {
+            FilterType::Box => 0.5,
+            FilterType::Tent => 1.0,
+            FilterType::Gaussian => 1.5,
+            FilterType::Mitchell => 2.0,
+            FilterType::Lanczos => 3.0,
+        }
+    }
+}
+
+impl FromStr for FilterType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = crate::normalize_name(s);
+
+        match name.as_str() {
+            "box" => Ok(FilterType::Box),
+            "tent" | "triangle" => Ok(FilterType::Tent),
+            "gaussian" => Ok(FilterType::Gaussian),
+            "mitchell" | "mitchellnetravali" => Ok(FilterType::Mitchell),
+            "lanczos" => Ok(FilterType::Lanczos),
+            _ => Err(format!("Unknown filter '{}'", s)),
+        }
+    }
+}
+
+// Falloff of the gaussian, in 1 / pixels².
+const GAUSSIAN_ALPHA: f64 = 2.0;
+
+const MITCHELL_B: f64 = 1.0 / 3.0;
+const MITCHELL_C: f64 = 1.0 / 3.0;
+
+/// https://en.wikipedia.org/wiki/Mitchell%E2%80%93Netravali_filters
+/// `x` is scaled so the filter reaches 0 at 2.
+fn mitchell(x: f64) -> f64 {
+    let (b, c) = (MITCHELL_B, MITCHELL_C);
+    let x = x.abs();
+
+    let value = if x < 1.0 {
+        ((12.0 - (9.0 * b) - (6.0 * c)) * x * x * x) + ((-18.0 + (12.0 * b) + (6.0 * c)) * x * x) + (6.0 - (2.0 * b))
+    } else if x < 2.0 {
+        ((-b - (6.0 * c)) * x * x * x)
+            + (((6.0 * b) + (30.0 * c)) * x * x)
+            + (((-12.0 * b) - (48.0 * c)) * x)
+            + ((8.0 * b) + (24.0 * c))
+    } else {
+        0.0
+    };
+
+    value / 6.0
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+
+    (PI * x).sin() / (PI * x)
+}
+
+/// A reconstruction filter with its radius worked out.
+#[derive(Debug, Copy, Clone)]
+pub struct Filter {
+    pub kind: FilterType,
+    /// In pixels, samples further than this from a pixel's center (along
+    /// either axis) don't count towards it.
+    pub radius: f64,
+}
+
+impl Filter {
+    pub fn new(kind: FilterType, radius: Option<f64>) -> Self {
+        Filter {
+            kind,
+            radius: radius.unwrap_or_else(|| kind.default_radius()),
+        }
+    }
+
+    /// How many pixels either side of a pixel can have samples that count
+    /// towards it.
+    pub fn margin(&self) -> u32 {
+        (self.radius - 0.5).ceil().max(0.0) as u32
+    }
+
+    /// Weight of a sample `x` & `y` pixels from the center of a pixel. The
+    /// filters are separable so this is the product of the weight along each
+    /// axis.
+    pub fn weight(&self, x: f64, y: f64) -> f64 {
+        if x.abs() > self.radius || y.abs() > self.radius {
+            return 0.0;
+        }
+
+        self.weight_1d(x) * self.weight_1d(y)
+    }
+
+    fn weight_1d(&self, x: f64) -> f64 {
+        let radius = self.radius;
+
+        match self.kind {
+            FilterType::Box => 1.0,
+            FilterType::Tent => f64::max(0.0, radius - x.abs()),
+            FilterType::Gaussian => {
+                // Shifted down so it reaches 0 at the radius instead of
+                // being cut off.
+                let edge = (-GAUSSIAN_ALPHA * radius * radius).exp();
+                f64::max(0.0, (-GAUSSIAN_ALPHA * x * x).exp() - edge)
+            }
+            FilterType::Mitchell => mitchell(2.0 * x / radius),
+            FilterType::Lanczos => sinc(x) * sinc(x / radius),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [FilterType; 5] = [
+        FilterType::Box,
+        FilterType::Tent,
+        FilterType::Gaussian,
+        FilterType::Mitchell,
+        FilterType::Lanczos,
+    ];
+
+    #[test]
+    fn margins_cover_the_radius() {
+        let margins: Vec<u32> = ALL.iter().map(|kind| Filter::new(*kind, None).margin()).collect();
+        assert_eq!(margins, [0, 1, 1, 2, 3]);
+
+        let radii: Vec<f64> = ALL.iter().map(|kind| Filter::new(*kind, None).radius).collect();
+        assert_eq!(radii, [0.5, 1.0, 1.5, 2.0, 3.0]);
+
+        assert_eq!(Filter::new(FilterType::Tent, Some(0.3)).margin(), 0);
+        assert_eq!(Filter::new(FilterType::Tent, Some(0.5)).margin(), 0);
+        assert_eq!(Filter::new(FilterType::Tent, Some(0.51)).margin(), 1);
+        assert_eq!(Filter::new(FilterType::Lanczos, Some(2.5)).margin(), 2);
+    }
+
+    #[test]
+    fn weights_at_the_center_and_radius() {
+        let gaussian_edge = (-GAUSSIAN_ALPHA * 1.5 * 1.5).exp();
+        let centers = [1.0, 1.0, 1.0 - gaussian_edge, 8.0 / 9.0, 1.0];
+
+        for (kind, center) in ALL.iter().zip(centers.iter()) {
+            let filter = Filter::new(*kind, None);
+            let radius = filter.radius;
+
+            assert!((filter.weight(0.0, 0.0) - (center * center)).abs() < 1e-12, "{:?}", kind);
+            assert_eq!(filter.weight(radius + 1e-9, 0.0), 0.0, "{:?}", kind);
+            assert_eq!(filter.weight(0.0, -radius - 1e-9), 0.0, "{:?}", kind);
+
+            // Only the box doesn't fall off to 0 at its edge.
+            let edge = filter.weight(radius, 0.0);
+            if *kind == FilterType::Box {
+                assert_eq!(edge, 1.0);
+            } else {
+                assert!(edge.abs() < 1e-12, "{:?} is {} at the radius", kind, edge);
+            }
+        }
+    }
+
+    #[test]
+    fn negative_lobes() {
+        let lowest = |kind: FilterType| {
+            let filter = Filter::new(kind, None);
+
+            (0..=300)
+                .map(|i| filter.weight(filter.radius * (i as f64) / 300.0, 0.0))
+                .fold(f64::INFINITY, f64::min)
+        };
+
+        assert!(lowest(FilterType::Mitchell) < 0.0);
+        assert!(lowest(FilterType::Lanczos) < 0.0);
+
+        for kind in [FilterType::Box, FilterType::Tent, FilterType::Gaussian].iter() {
+            assert!(lowest(*kind) >= 0.0, "{:?}", kind);
+        }
+
+        // Mitchell dips between half its radius and the radius, Lanczos
+        // between the first and second zero of the sinc.
+        assert!(Filter::new(FilterType::Mitchell, None).weight(1.5, 0.0) < 0.0);
+        assert!(Filter::new(FilterType::Lanczos, None).weight(1.5, 0.0) < 0.0);
+        assert!(Filter::new(FilterType::Lanczos, None).weight(2.5, 0.0) > 0.0);
+    }
+}
@@ -0,0 +1,898 @@
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+
+use super::render::Canvas;
+use super::Float3;
+
+fn to_u8(value: f64) -> u8 {
+    (value * 255.0).clamp(0.0, 255.0) as u8
+}
+
+/// https://en.wikipedia.org/wiki/SRGB#From_CIE_XYZ_to_sRGB
+fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.003_130_8 {
+        12.92 * value
+    } else {
+        (1.055 * value.powf(1.0 / 2.4)) - 0.055
+    }
+}
+
+/// Converts to IEEE 754 half precision, rounding to nearest even.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+
+    if exponent == 0xff {
+        // Infinity or NaN, keep NaNs quiet.
+        let nan_bit = if mantissa != 0 { 0x0200 } else { 0 };
+        return sign | 0x7c00 | nan_bit;
+    }
+
+    let exponent = exponent - 127 + 15;
+
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if exponent <= 0 {
+        // Subnormal in half precision (or too small and flushed to zero).
+        if exponent < -10 {
+            return sign;
+        }
+
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - exponent) as u32;
+        let round_bit = 1 << (shift - 1);
+        let mut half_mantissa = (mantissa >> shift) as u16;
+
+        if (mantissa & round_bit) != 0 && (mantissa & ((3 * round_bit) - 1)) != 0 {
+            half_mantissa += 1;
+        }
+
+        return sign | half_mantissa;
+    }
+
+    let half = sign | ((exponent as u16) << 10) | ((mantissa >> 13) as u16);
+    let round_bit = 0x1000;
+
+    // A carry out of the mantissa correctly bumps the exponent.
+    if (mantissa & round_bit) != 0 && (mantissa & ((3 * round_bit) - 1)) != 0 {
+        half + 1
+    } else {
+        half
+    }
+}
+
+/// Shared exponent encoding used by Radiance .hdr files.
+fn float3_to_rgbe(color: &Float3) -> [u8; 4] {
+    let r = f64::max(0.0, color.x);
+    let g = f64::max(0.0, color.y);
+    let b = f64::max(0.0, color.z);
+
+    let v = f64::max(r, f64::max(g, b));
+
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // frexp: v = m * 2^e with m in [0.5, 1)
+    let mut e = v.log2().floor() as i32 + 1;
+    if v / 2f64.powi(e) >= 1.0 {
+        e += 1;
+    }
+
+    let scale = 256.0 / 2f64.powi(e);
+
+    [
+        f64::min(255.0, r * scale) as u8,
+        f64::min(255.0, g * scale) as u8,
+        f64::min(255.0, b * scale) as u8,
+        (e + 128) as u8,
+    ]
+}
+
+fn rgbe_to_float3(rgbe: &[u8]) -> Float3 {
+    if rgbe[3] == 0 {
+        return Float3::new(0.0, 0.0, 0.0);
+    }
+
+    // Each value was rounded down when written, so use the middle of its step.
+    let scale = 2f64.powi(rgbe[3] as i32 - 136);
+
+    Float3::new(
+        (rgbe[0] as f64 + 0.5) * scale,
+        (rgbe[1] as f64 + 0.5) * scale,
+        (rgbe[2] as f64 + 0.5) * scale,
+    )
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Reads one header line without the newline.
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+
+    if reader.read_line(&mut line)? == 0 {
+        return Err(invalid_data("unexpected end of file in the header"));
+    }
+
+    Ok(line.trim_end_matches(['\n', '\r']).to_string())
+}
+
+// Reads everything after the header, which has to be at least `min_size`
+// bytes (`None` if working that out overflowed) so a bad header can't ask for
+// a huge image.
+fn read_payload<R: Read>(reader: &mut R, min_size: Option<usize>) -> io::Result<Vec<u8>> {
+    let min_size = min_size.ok_or_else(|| invalid_data("image is too big"))?;
+
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    if data.len() < min_size {
+        return Err(invalid_data("file is shorter than its header says"));
+    }
+
+    Ok(data)
+}
+
+// One scanline of an .hdr file, either flat or run length encoded per channel.
+fn read_hdr_scanline<R: Read>(reader: &mut R, width: usize, out: &mut [u8]) -> io::Result<()> {
+    let mut start = [0u8; 4];
+    reader.read_exact(&mut start)?;
+
+    let encoded = (8..0x8000).contains(&width) && start[0] == 2 && start[1] == 2 && (start[2] & 0x80) == 0;
+
+    if !encoded {
+        out[..4].copy_from_slice(&start);
+        return reader.read_exact(&mut out[4..]);
+    }
+
+    if ((start[2] as usize) << 8 | start[3] as usize) != width {
+        return Err(invalid_data("scanline width doesn't match the image"));
+    }
+
+    // The channels come one after the other, each as runs & literal spans.
+    for channel in 0..4 {
+        let mut x = 0;
+
+        while x < width {
+            let mut count = [0u8; 1];
+            reader.read_exact(&mut count)?;
+
+            let (count, run) = if count[0] > 128 {
+                ((count[0] - 128) as usize, true)
+            } else {
+                (count[0] as usize, false)
+            };
+
+            if count == 0 || x + count > width {
+                return Err(invalid_data("bad run length in scanline"));
+            }
+
+            let mut value = [0u8; 1];
+            for i in 0..count {
+                if i == 0 || !run {
+                    reader.read_exact(&mut value)?;
+                }
+
+                out[(x + i) * 4 + channel] = value[0];
+            }
+
+            x += count;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExrPixelType {
+    Half,
+    Float,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImageFormat {
+    /// 8-bit sRGB PNG.
+    Png,
+    /// 8-bit binary PPM (P6), quantised the same way as the window.
+    Ppm,
+    /// Little-endian 32-bit float PFM.
+    Pfm,
+    /// Headerless little-endian f32 RGB triplets, rows top to bottom.
+    Raw,
+    /// Uncompressed scanline OpenEXR holding the unclamped linear values.
+    Exr(ExrPixelType),
+    /// Radiance RGBE holding the unclamped linear values.
+    Hdr,
+}
+
+impl ImageFormat {
+    pub fn from_filename(filename: &str) -> Option<ImageFormat> {
+        let extension = Path::new(filename).extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            "pfm" => Some(ImageFormat::Pfm),
+            "raw" | "f32" => Some(ImageFormat::Raw),
+            "exr" => Some(ImageFormat::Exr(ExrPixelType::Half)),
+            "hdr" => Some(ImageFormat::Hdr),
+            _ => None,
+        }
+    }
+}
+
+/// In-memory framebuffer used when rendering without a window.
+///
+/// Pixels are stored at full precision in renderer coordinates, (0,0) is the
+/// bottom left.
+#[derive(Debug, Clone)]
+pub struct ImageBuffer {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<Float3>,
+}
+
+impl ImageBuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        ImageBuffer {
+            width,
+            height,
+            pixels: vec![Float3::new(0.0, 0.0, 0.0); (width as usize) * (height as usize)],
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        ((y as usize) * (self.width as usize)) + (x as usize)
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> Float3 {
+        self.pixels[self.index(x, y)]
+    }
+
+    /// Reads a Radiance .hdr or .pfm image, the formats are picked from the
+    /// extension.
+    pub fn from_file(filename: &str) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(filename)?);
+
+        match ImageFormat::from_filename(filename) {
+            Some(ImageFormat::Hdr) => Self::read_hdr(&mut reader),
+            Some(ImageFormat::Pfm) => Self::read_pfm(&mut reader),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Can't read '{}', only .hdr and .pfm images can be loaded", filename),
+            )),
+        }
+    }
+
+    /// Reads files like `write_hdr` writes, as well as run length encoded
+    /// ones. Only the usual `-Y <height> +X <width>` orientation is handled.
+    pub fn read_hdr<R: BufRead>(reader: &mut R) -> io::Result<Self> {
+        if !read_line(reader)?.starts_with("#?") {
+            return Err(invalid_data("not a Radiance .hdr file"));
+        }
+
+        // Variables run up to an empty line.
+        loop {
+            let line = read_line(reader)?;
+
+            if line.is_empty() {
+                break;
+            }
+
+            if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+                return Err(invalid_data("only 32-bit_rle_rgbe .hdr files are supported"));
+            }
+        }
+
+        let resolution = read_line(reader)?;
+        let parts: Vec<&str> = resolution.split_whitespace().collect();
+
+        let (height, width) = match parts.as_slice() {
+            ["-Y", height, "+X", width] => (
+                height.parse::<u32>().map_err(|_| invalid_data("bad image height"))?,
+                width.parse::<u32>().map_err(|_| invalid_data("bad image width"))?,
+            ),
+            _ => return Err(invalid_data("only `-Y <height> +X <width>` .hdr files are supported")),
+        };
+
+        // Scanlines are at least a byte a pixel flat, run length encoded
+        // ones need 2 bytes a run of up to 127 in each channel.
+        let min_line_size = if (8..0x8000).contains(&width) {
+            4 + (8 * (width as usize).div_ceil(127))
+        } else {
+            4 * (width as usize)
+        };
+        let data = read_payload(reader, min_line_size.checked_mul(height as usize))?;
+        let mut payload = &data[..];
+
+        let mut image = ImageBuffer::new(width, height);
+        let mut scanline = vec![0u8; (width * 4) as usize];
+
+        // -Y means rows go from top to bottom.
+        for y in (0..height).rev() {
+            read_hdr_scanline(&mut payload, width as usize, &mut scanline)?;
+
+            for x in 0..width {
+                let start = (x * 4) as usize;
+                image.set_pixel(x, y, &rgbe_to_float3(&scanline[start..start + 4]));
+            }
+        }
+
+        Ok(image)
+    }
+
+    /// Reads color (`PF`) files in either byte order.
+    pub fn read_pfm<R: BufRead>(reader: &mut R) -> io::Result<Self> {
+        if read_line(reader)? != "PF" {
+            return Err(invalid_data("only color (PF) .pfm files are supported"));
+        }
+
+        let size = read_line(reader)?;
+        let mut size = size.split_whitespace().map(|value| value.parse::<u32>());
+
+        let (width, height) = match (size.next(), size.next()) {
+            (Some(Ok(width)), Some(Ok(height))) => (width, height),
+            _ => return Err(invalid_data("bad image size")),
+        };
+
+        let scale: f64 = read_line(reader)?
+            .trim()
+            .parse()
+            .map_err(|_| invalid_data("bad scale"))?;
+
+        let size = (width as usize).checked_mul(height as usize).and_then(|pixels| pixels.checked_mul(12));
+        let data = read_payload(reader, size)?;
+        let mut payload = &data[..];
+
+        let mut image = ImageBuffer::new(width, height);
+        let mut value = [0u8; 4];
+        let mut read_f32 = |reader: &mut &[u8]| -> io::Result<f64> {
+            reader.read_exact(&mut value)?;
+
+            // A negative scale marks the data as little-endian.
+            Ok(if scale < 0.0 {
+                f32::from_le_bytes(value) as f64
+            } else {
+                f32::from_be_bytes(value) as f64
+            })
+        };
+
+        // PFM rows go from bottom to top, same as the renderer.
+        for y in 0..height {
+            for x in 0..width {
+                let color = Float3::new(read_f32(&mut payload)?, read_f32(&mut payload)?, read_f32(&mut payload)?);
+                image.set_pixel(x, y, &(color * scale.abs()));
+            }
+        }
+
+        Ok(image)
+    }
+
+    /// Writes the image, picking the format from the file extension.
+    pub fn to_file(&self, filename: &str) -> io::Result<()> {
+        let format = ImageFormat::from_filename(filename).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown image format for '{}'", filename),
+            )
+        })?;
+
+        self.to_file_with_format(filename, format)
+    }
+
+    pub fn to_file_with_format(&self, filename: &str, format: ImageFormat) -> io::Result<()> {
+        let file = File::create(filename)?;
+        let mut writer = BufWriter::new(file);
+
+        match format {
+            ImageFormat::Png => self.write_png(&mut writer)?,
+            ImageFormat::Ppm => self.write_ppm(&mut writer)?,
+            ImageFormat::Pfm => self.write_pfm(&mut writer)?,
+            ImageFormat::Raw => self.write_raw(&mut writer)?,
+            ImageFormat::Exr(pixel_type) => self.write_exr(&mut writer, pixel_type)?,
+            ImageFormat::Hdr => self.write_hdr(&mut writer)?,
+        }
+
+        writer.flush()
+    }
+
+    pub fn write_png<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+
+        let mut data = Vec::with_capacity(self.pixels.len() * 3);
+
+        // PNG rows go from top to bottom.
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let color = self.get_pixel(x, y);
+                data.push(to_u8(linear_to_srgb(color.x)));
+                data.push(to_u8(linear_to_srgb(color.y)));
+                data.push(to_u8(linear_to_srgb(color.z)));
+            }
+        }
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+
+        Ok(())
+    }
+
+    pub fn write_ppm<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+
+        // PPM rows go from top to bottom.
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let color = self.get_pixel(x, y);
+                writer.write_all(&[to_u8(color.x), to_u8(color.y), to_u8(color.z)])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// http://www.pauldebevec.com/Research/HDR/PFM/
+    pub fn write_pfm<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        // A negative scale marks the data as little-endian.
+        write!(writer, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+
+        // PFM rows go from bottom to top, same as the renderer.
+        for y in 0..self.height {
+            for x in 0..self.width {
+                write_f32_le(&mut writer, &self.get_pixel(x, y))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn write_raw<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                write_f32_le(&mut writer, &self.get_pixel(x, y))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// https://www.openexr.com/documentation/openexrfilelayout.pdf
+    ///
+    /// Writes a single part scanline file with no compression, one scanline
+    /// per chunk.
+    pub fn write_exr<W: Write>(&self, mut writer: W, pixel_type: ExrPixelType) -> io::Result<()> {
+        let (type_id, bytes_per_value) = match pixel_type {
+            ExrPixelType::Half => (1i32, 2usize),
+            ExrPixelType::Float => (2i32, 4usize),
+        };
+
+        // Magic number followed by version 2, single part scanline.
+        writer.write_all(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0])?;
+
+        // Channels must be sorted by name.
+        let mut channels = Vec::new();
+        for name in ["B", "G", "R"].iter() {
+            channels.extend_from_slice(name.as_bytes());
+            channels.push(0);
+            channels.extend_from_slice(&type_id.to_le_bytes());
+            // pLinear and reserved bytes
+            channels.extend_from_slice(&[0, 0, 0, 0]);
+            // x & y sampling
+            channels.extend_from_slice(&1i32.to_le_bytes());
+            channels.extend_from_slice(&1i32.to_le_bytes());
+        }
+        channels.push(0);
+
+        let mut window = Vec::new();
+        for value in [0, 0, self.width as i32 - 1, self.height as i32 - 1].iter() {
+            window.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut header = Vec::new();
+        write_exr_attribute(&mut header, "channels", "chlist", &channels);
+        write_exr_attribute(&mut header, "compression", "compression", &[0]);
+        write_exr_attribute(&mut header, "dataWindow", "box2i", &window);
+        write_exr_attribute(&mut header, "displayWindow", "box2i", &window);
+        write_exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        write_exr_attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+        write_exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+        write_exr_attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+        header.push(0);
+
+        writer.write_all(&header)?;
+
+        let line_size = self.width as usize * 3 * bytes_per_value;
+        let chunk_size = (4 + 4 + line_size) as u64;
+        let first_chunk = (8 + header.len() + (8 * self.height as usize)) as u64;
+
+        for i in 0..(self.height as u64) {
+            writer.write_all(&(first_chunk + (i * chunk_size)).to_le_bytes())?;
+        }
+
+        let mut line = Vec::with_capacity(line_size);
+
+        // Increasing y goes from top to bottom.
+        for line_y in 0..self.height {
+            let y = self.height - 1 - line_y;
+
+            line.clear();
+            for channel in 0..3 {
+                // B, G, R
+                let channel = 2 - channel;
+
+                for x in 0..self.width {
+                    let value = self.get_pixel(x, y)[channel] as f32;
+
+                    match pixel_type {
+                        ExrPixelType::Half => line.extend_from_slice(&f32_to_f16(value).to_le_bytes()),
+                        ExrPixelType::Float => line.extend_from_slice(&value.to_le_bytes()),
+                    }
+                }
+            }
+
+            writer.write_all(&(line_y as i32).to_le_bytes())?;
+            writer.write_all(&(line_size as i32).to_le_bytes())?;
+            writer.write_all(&line)?;
+        }
+
+        Ok(())
+    }
+
+    /// https://www.graphics.cornell.edu/~bjw/rgbe.html
+    ///
+    /// Scanlines are written flat (not run length encoded).
+    pub fn write_hdr<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(
+            writer,
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            self.height, self.width
+        )?;
+
+        // -Y means rows go from top to bottom.
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                writer.write_all(&float3_to_rgbe(&self.get_pixel(x, y)))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_exr_attribute(header: &mut Vec<u8>, name: &str, type_name: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(type_name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+fn write_f32_le<W: Write>(writer: &mut W, color: &Float3) -> io::Result<()> {
+    writer.write_all(&(color.x as f32).to_le_bytes())?;
+    writer.write_all(&(color.y as f32).to_le_bytes())?;
+    writer.write_all(&(color.z as f32).to_le_bytes())
+}
+
+impl Canvas for ImageBuffer {
+    fn set_pixel(&mut self, x: u32, y: u32, color: &Float3) {
+        let index = self.index(x, y);
+        self.pixels[index] = *color;
+    }
+
+    fn present(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every pixel is different and some are outside [0, 1].
+    fn test_image(width: u32, height: u32) -> ImageBuffer {
+        let mut image = ImageBuffer::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let color = Float3::new(x as f64 * 0.25, y as f64 * 0.5, 1.5 - (x + y) as f64 * 0.125);
+                image.set_pixel(x, y, &color);
+            }
+        }
+
+        image
+    }
+
+    fn f32s(bytes: &[u8]) -> Vec<f32> {
+        bytes
+            .chunks(4)
+            .map(|value| f32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+            .collect()
+    }
+
+    // Values in the order `write_raw` writes them, rows top to bottom.
+    fn top_to_bottom(image: &ImageBuffer) -> Vec<f32> {
+        let mut values = Vec::new();
+
+        for y in (0..image.height).rev() {
+            for x in 0..image.width {
+                values.extend(image.get_pixel(x, y).iter().map(|c| *c as f32));
+            }
+        }
+
+        values
+    }
+
+    #[test]
+    fn raw_is_rows_top_to_bottom() {
+        let image = test_image(3, 2);
+        let mut data = Vec::new();
+        image.write_raw(&mut data).unwrap();
+
+        assert_eq!(f32s(&data), top_to_bottom(&image));
+    }
+
+    #[test]
+    fn pfm_is_rows_bottom_to_top() {
+        let image = test_image(3, 2);
+        let mut data = Vec::new();
+        image.write_pfm(&mut data).unwrap();
+
+        let header = b"PF\n3 2\n-1.0\n";
+        assert_eq!(&data[..header.len()], &header[..]);
+
+        let values = f32s(&data[header.len()..]);
+        assert_eq!(values.len(), 3 * 3 * 2);
+
+        // The first row written is the bottom one.
+        assert_eq!(values[0..3], [0.0, 0.0, 1.5]);
+        assert_eq!(values[9..12], [0.0, 0.5, 1.375]);
+    }
+
+    #[test]
+    fn ppm_is_clamped_bytes_top_to_bottom() {
+        let image = test_image(3, 2);
+        let mut data = Vec::new();
+        image.write_ppm(&mut data).unwrap();
+
+        let header = b"P6\n3 2\n255\n";
+        assert_eq!(&data[..header.len()], &header[..]);
+
+        let expected: Vec<u8> = top_to_bottom(&image).iter().map(|c| to_u8(*c as f64)).collect();
+        assert_eq!(&data[header.len()..], &expected[..]);
+        assert_eq!(data[header.len() + 2], 255);
+    }
+
+    #[test]
+    fn png_decodes_to_srgb() {
+        let image = test_image(3, 2);
+        let mut data = Vec::new();
+        image.write_png(&mut data).unwrap();
+
+        let decoder = png::Decoder::new(&data[..]);
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+
+        assert_eq!((info.width, info.height), (3, 2));
+        assert_eq!(info.color_type, png::ColorType::Rgb);
+
+        let expected: Vec<u8> = top_to_bottom(&image)
+            .iter()
+            .map(|c| to_u8(linear_to_srgb(*c as f64)))
+            .collect();
+        assert_eq!(&pixels[..info.buffer_size()], &expected[..]);
+    }
+
+    #[test]
+    fn f16_conversion() {
+        assert_eq!(f32_to_f16(0.0), 0x0000);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(0.5), 0x3800);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+
+        // Too big, infinite & NaN.
+        assert_eq!(f32_to_f16(65536.0), 0x7c00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NAN) & 0x7e00, 0x7e00);
+
+        // Smallest subnormal, and half of it rounds to even (zero).
+        assert_eq!(f32_to_f16(2f32.powi(-24)), 0x0001);
+        assert_eq!(f32_to_f16(2f32.powi(-25)), 0x0000);
+
+        // Halfway between 1 and the next half rounds to even, past it rounds up.
+        assert_eq!(f32_to_f16(1.0 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(f32_to_f16(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+    }
+
+    #[test]
+    fn rgbe_conversion() {
+        assert_eq!(float3_to_rgbe(&Float3::new(0.0, 0.0, 0.0)), [0, 0, 0, 0]);
+        assert_eq!(float3_to_rgbe(&Float3::new(-1.0, 0.0, 0.0)), [0, 0, 0, 0]);
+        assert_eq!(float3_to_rgbe(&Float3::new(1.0, 0.5, 0.25)), [128, 64, 32, 129]);
+
+        for color in [Float3::new(0.3, 0.02, 7.0), Float3::new(1000.0, 999.0, 0.001)].iter() {
+            let decoded = rgbe_to_float3(&float3_to_rgbe(color));
+            let largest = color.max();
+
+            // Within a step of the shared exponent's mantissa.
+            for i in 0..3 {
+                assert!((decoded[i] - color[i]).abs() <= largest / 256.0, "{} came back as {}", color, decoded);
+            }
+        }
+    }
+
+    #[test]
+    fn exr_offsets_point_at_scanlines() {
+        let image = test_image(3, 2);
+
+        for (pixel_type, bytes_per_value) in [(ExrPixelType::Half, 2), (ExrPixelType::Float, 4)].iter() {
+            let mut data = Vec::new();
+            image.write_exr(&mut data, *pixel_type).unwrap();
+
+            assert_eq!(data[0..4], [0x76, 0x2f, 0x31, 0x01]);
+
+            let line_size = 3 * 3 * bytes_per_value;
+            let chunk_size = 4 + 4 + line_size;
+
+            // The offset table comes right before the first scanline.
+            let first_chunk = data.len() - (2 * chunk_size);
+            let table = first_chunk - 16;
+
+            for line_y in 0..2 {
+                let mut offset = [0u8; 8];
+                offset.copy_from_slice(&data[(table + (8 * line_y))..(table + (8 * line_y) + 8)]);
+                let offset = u64::from_le_bytes(offset) as usize;
+                assert_eq!(offset, first_chunk + (line_y * chunk_size));
+
+                let y = i32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+                assert_eq!(y, line_y as i32);
+            }
+
+            if *pixel_type == ExrPixelType::Float {
+                // Blue of the top row, y = 1 in the renderer.
+                let blue = f32s(&data[(first_chunk + 8)..(first_chunk + 8 + 12)]);
+                assert_eq!(blue, [1.375, 1.25, 1.125]);
+            }
+        }
+    }
+
+    fn temp_file(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("raytracer-test-{}-{}", std::process::id(), name));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn pfm_round_trip() {
+        let image = test_image(5, 3);
+        let filename = temp_file("round-trip.pfm");
+
+        image.to_file(&filename).unwrap();
+        let read = ImageBuffer::from_file(&filename);
+        std::fs::remove_file(&filename).unwrap();
+        let read = read.unwrap();
+
+        assert_eq!((read.width, read.height), (5, 3));
+
+        // The test image's values are all exact in f32.
+        for y in 0..3 {
+            for x in 0..5 {
+                assert_eq!(read.get_pixel(x, y), image.get_pixel(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn pfm_reads_big_endian_and_scale() {
+        let mut data = b"PF\n2 1\n2.0\n".to_vec();
+        for value in [1.0f32, 2.0, 3.0, 0.25, 0.5, 0.75].iter() {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+
+        let image = ImageBuffer::read_pfm(&mut &data[..]).unwrap();
+
+        assert_eq!(image.get_pixel(0, 0), Float3::new(2.0, 4.0, 6.0));
+        assert_eq!(image.get_pixel(1, 0), Float3::new(0.5, 1.0, 1.5));
+    }
+
+    #[test]
+    fn hdr_round_trip() {
+        let image = test_image(9, 4);
+        let filename = temp_file("round-trip.hdr");
+
+        image.to_file(&filename).unwrap();
+        let read = ImageBuffer::from_file(&filename);
+        std::fs::remove_file(&filename).unwrap();
+        let read = read.unwrap();
+
+        assert_eq!((read.width, read.height), (9, 4));
+
+        for y in 0..4 {
+            for x in 0..9 {
+                let expected = image.get_pixel(x, y).map(|c| c.max(0.0));
+                let largest = expected.max();
+
+                assert!(
+                    (read.get_pixel(x, y) - expected).amax() <= largest / 256.0,
+                    "({}, {}) is {} instead of {}",
+                    x,
+                    y,
+                    read.get_pixel(x, y),
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn hdr_reads_run_length_encoding() {
+        let mut data = b"#?RADIANCE\n# comment\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+
+        // Scanline header, then red as a run of 8, green as 8 literals, blue
+        // as two runs of 4 and the exponent as a run of 8.
+        data.extend_from_slice(&[2, 2, 0, 8]);
+        data.extend_from_slice(&[128 + 8, 128]);
+        data.extend_from_slice(&[8, 0, 16, 32, 48, 64, 80, 96, 112]);
+        data.extend_from_slice(&[128 + 4, 64, 128 + 4, 0]);
+        data.extend_from_slice(&[128 + 8, 129]);
+
+        let image = ImageBuffer::read_hdr(&mut &data[..]).unwrap();
+
+        assert_eq!((image.width, image.height), (8, 1));
+
+        for x in 0..8 {
+            let expected = rgbe_to_float3(&[128, (16 * x) as u8, if x < 4 { 64 } else { 0 }, 129]);
+            assert_eq!(image.get_pixel(x, 0), expected);
+        }
+    }
+
+    #[test]
+    fn readers_reject_other_files() {
+        assert!(ImageBuffer::read_pfm(&mut &b"Pf\n1 1\n-1.0\n"[..]).is_err());
+        assert!(ImageBuffer::read_pfm(&mut &b"PF\n1 1\n-1.0\n\0\0"[..]).is_err());
+        assert!(ImageBuffer::read_hdr(&mut &b"P6\n1 1\n255\n"[..]).is_err());
+        assert!(ImageBuffer::read_hdr(&mut &b"#?RADIANCE\n\n+Y 1 +X 1\n"[..]).is_err());
+
+        // Only .hdr & .pfm files are read.
+        let filename = temp_file("other.png");
+        test_image(2, 2).to_file(&filename).unwrap();
+        let error = ImageBuffer::from_file(&filename);
+        std::fs::remove_file(&filename).unwrap();
+        assert_eq!(error.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn readers_check_the_size_against_the_file() {
+        let headers: [&[u8]; 4] = [
+            b"PF\n4294967295 4294967295\n-1.0\n",
+            b"PF\n100000 100000\n-1.0\n\0\0\0\0",
+            b"#?RADIANCE\n\n-Y 4294967295 +X 4294967295\n",
+            b"#?RADIANCE\n\n-Y 100000 +X 100000\n\x02\x02\x00\x08",
+        ];
+
+        for (i, header) in headers.iter().enumerate() {
+            let result = if i < 2 {
+                ImageBuffer::read_pfm(&mut &header[..])
+            } else {
+                ImageBuffer::read_hdr(&mut &header[..])
+            };
+
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
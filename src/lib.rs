@@ -5,71 +5,97 @@ use std::option::Option;
 
 pub type Float3 = na::Vector3<f64>;
 
+pub mod bvh;
+pub mod camera;
+pub mod emitter;
+pub mod environment;
+pub mod filter;
+pub mod image;
+pub mod lint;
 pub mod render;
+pub mod sampler;
 pub mod scene;
 pub mod shapes;
+pub mod sky;
+pub mod stereo;
+pub mod tiles;
+#[cfg(feature = "viewer")]
+pub mod viewer;
+
 pub use crate::scene::Scene;
+
 use crate::shapes::*;
 
+/// Lowercases `name` and drops '-' & '_', so `FromStr` for the settings enums
+/// accepts their serialized names as well as lower and kebab case versions.
+/// "SuperSample", "supersample" and "super-sample" are all "supersample".
+pub(crate) fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| *c != '-' && *c != '_')
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+/// For `skip_serializing_if`, so files written back out leave out what was
+/// left at its default.
+pub(crate) fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Intersection {
     pub t: f64,
     pub normal: Float3,
 }
 
-pub fn ray_vs_scene_helper(ray: &Ray, scene: &Scene, break_on_hit: bool, max_t: f64) -> Option<(Intersection, Material)> {
-    let mut t = max_t;
-    let mut out: Option<(Intersection, Material)> = None;
+/// Like `ray_vs_scene_helper` but gives the index of the shape hit in
+/// `scene.shapes`.
+pub fn ray_vs_scene_shape(ray: &Ray, scene: &Scene, break_on_hit: bool, max_t: f64) -> Option<(usize, Intersection)> {
+    let mut out: Option<(usize, Intersection)> = None;
 
-    for shape in scene.spheres.iter() {
-        if let Some(res) = ray_vs_sphere(&ray, &shape, t) {
-            t = res.t;
-            out = Some((res, shape.material));
+    scene.bvh.traverse(ray, max_t, break_on_hit, |index, t| {
+        let res = scene.shapes[index].intersect(ray, t)?;
 
-            if break_on_hit {
-                return out;
-            }
-        }
-    }
+        out = Some((index, res));
 
-    for shape in scene.ellipsoids.iter() {
-        if let Some(res) = ray_vs_ellipsoid(&ray, &shape, t) {
-            t = res.t;
-            out = Some((res, shape.material));
+        Some(res.t)
+    });
 
-            if break_on_hit {
-                return out;
-            }
-        }
-    }
+    out
+}
+
+pub fn ray_vs_scene_helper(ray: &Ray, scene: &Scene, break_on_hit: bool, max_t: f64) -> Option<(Intersection, Material)> {
+    ray_vs_scene_shape(ray, scene, break_on_hit, max_t).map(|(index, res)| (res, *scene.shapes[index].material()))
+}
 
-    for shape in scene.rhombohedrons.iter() {
-        if let Some(res) = ray_vs_rhombohedron(&ray, &shape, t) {
-            t = res.t;
-            out = Some((res, shape.material));
+/// What's in the way of a shadow ray, between its origin and its end.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Occlusion {
+    Clear,
+    /// Only shapes that let some light through (a `specular_coefficient`
+    /// above 0).
+    Transparent,
+    Opaque,
+}
 
-            if break_on_hit {
-                return out;
-            }
-        }
-    }
+/// Stops at the first opaque shape found, transparent ones are only noted.
+pub fn ray_vs_scene_shadow(ray: &Ray, scene: &Scene) -> Occlusion {
+    let mut occlusion = Occlusion::Clear;
 
-    for shape in scene.polygons.iter() {
-        if let Some(res) = ray_vs_polygon(&ray, &shape, t) {
-            t = res.t;
-            out = Some((res, shape.material));
+    scene.bvh.traverse(ray, 1.0, true, |index, t| {
+        let shape = &scene.shapes[index];
+        let res = shape.intersect(ray, t)?;
 
-            if break_on_hit {
-                return out;
-            }
+        if shape.material().specular_coefficient > 0.0 {
+            occlusion = Occlusion::Transparent;
+            return None;
         }
-    }
 
-    out
-}
+        occlusion = Occlusion::Opaque;
+        Some(res.t)
+    });
 
-pub fn ray_vs_scene_shadow(ray: &Ray, scene: &Scene) -> bool {
-    ray_vs_scene_helper(ray, scene, true, 1.0).is_some()
+    occlusion
 }
 
 pub fn ray_vs_scene(ray: &Ray, scene: &Scene) -> Option<(Intersection, Material)> {
@@ -110,23 +136,22 @@ fn ray_vs_sphere2(ray: &Ray, sphere: &Sphere) -> (u32, Vec<Intersection>) {
 }
 
 fn ray_vs_sphere(ray: &Ray, sphere: &Sphere, max_t: f64) -> Option<Intersection> {
-    let (count, result) = ray_vs_sphere2(&ray, &sphere);
+    let (count, result) = ray_vs_sphere2(ray, sphere);
 
-    if 2 == count {
-        if result[0].t < max_t {
-            return Some(result[0]);
-        }
-    } else if 1 == count {
-        if result[1].t < max_t {
-            return Some(result[1]);
-        }
+    if 2 == count && result[0].t < max_t {
+        return Some(result[0]);
+    } else if 1 == count && result[1].t < max_t {
+        return Some(result[1]);
     }
 
     None
 }
 
 fn ray_vs_rhombohedron(ray: &Ray, rhombohedron: &Rhombohedron, max_t: f64) -> Option<Intersection> {
-    let mut t: [f64; 2] = [0.0, max_t];
+    // Start the exit at infinity rather than `max_t`, otherwise a ray starting
+    // inside that exits past `max_t` reports a hit at exactly `max_t` and the
+    // result depends on the order shapes are tested in.
+    let mut t: [f64; 2] = [0.0, f64::MAX];
     let mut normals: [Float3; 2] = [Float3::new(0.0, 0.0, 0.0), Float3::new(0.0, 0.0, 0.0)];
 
     for plane in rhombohedron.planes.iter() {
@@ -189,12 +214,7 @@ fn ray_vs_plane(ray: &Ray, plane: &Plane, max_t: f64) -> Option<Intersection> {
 }
 
 fn ray_vs_polygon(ray: &Ray, polygon: &Polygon, max_t: f64) -> Option<Intersection> {
-    let result = ray_vs_plane(&ray, &polygon.plane, max_t);
-    if result.is_none() {
-        return None;
-    }
-
-    let intersection = result.unwrap();
+    let intersection = ray_vs_plane(ray, &polygon.plane, max_t)?;
 
     let scaled_direction = Float3::new(
         ray.direction.x * intersection.t,
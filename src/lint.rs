@@ -0,0 +1,732 @@
+use std::fmt;
+
+use super::scene::{AntiAliasType, SceneFile};
+use super::shapes::*;
+use super::Float3;
+
+// Relative tolerance used when comparing stored values against the ones
+// derived from them.
+const TOLERANCE: f64 = 1e-6;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Probably not what was intended but renders fine.
+    Warning,
+    /// The shape won't render correctly.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found in a scene file.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Where in the json the problem is, e.g. `polygons[0].triangles[1].normal`.
+    pub path: String,
+    pub message: String,
+    /// The value can be derived from others in the file and `fix` will repair it.
+    pub fixable: bool,
+    /// Set when `fix` has repaired it.
+    pub fixed: bool,
+}
+
+struct Linter {
+    fix: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter {
+    fn report(&mut self, severity: Severity, path: String, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            path,
+            message,
+            fixable: false,
+            fixed: false,
+        });
+    }
+
+    // Returns true if the caller should apply the fix.
+    fn report_fixable(&mut self, severity: Severity, path: String, message: String) -> bool {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            path,
+            message,
+            fixable: true,
+            fixed: self.fix,
+        });
+
+        self.fix
+    }
+}
+
+/// Checks a scene for mistakes that parse fine but are probably wrong.
+pub fn lint(file: &SceneFile) -> Vec<Diagnostic> {
+    let mut file = file.clone();
+
+    run(&mut file, false)
+}
+
+/// Same as `lint` but repairs everything that can be derived from the rest of
+/// the file, those diagnostics are returned with `fixed` set.
+pub fn fix(file: &mut SceneFile) -> Vec<Diagnostic> {
+    run(file, true)
+}
+
+fn run(file: &mut SceneFile, fix: bool) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        fix,
+        diagnostics: Vec::new(),
+    };
+
+    if file.width == 0 {
+        linter.report(Severity::Error, "width".to_string(), "must be at least 1".to_string());
+    }
+
+    if file.height == 0 {
+        linter.report(Severity::Error, "height".to_string(), "must be at least 1".to_string());
+    }
+
+    if file.aa_rate == 0
+        && linter.report_fixable(Severity::Error, "aa_rate".to_string(), "must be at least 1".to_string())
+    {
+        file.aa_rate = 1;
+    }
+
+    if file.shadow_samples == 0
+        && linter.report_fixable(Severity::Error, "shadow_samples".to_string(), "must be at least 1".to_string())
+    {
+        file.shadow_samples = 1;
+    }
+
+    check_camera(&mut linter, file);
+
+    for (i, shape) in file.shapes.iter_mut().enumerate() {
+        let path = format!("shapes[{}]", i);
+
+        match shape {
+            Primitive::Sphere(sphere) => check_sphere(&mut linter, &path, sphere),
+            Primitive::Rhombohedron(rhombohedron) => check_rhombohedron(&mut linter, &path, rhombohedron),
+            Primitive::Polygon(polygon) => check_polygon(&mut linter, &path, polygon),
+            Primitive::Ellipsoid(ellipsoid) => check_ellipsoid(&mut linter, &path, ellipsoid),
+        }
+    }
+
+    for (i, sphere) in file.spheres.iter_mut().enumerate() {
+        check_sphere(&mut linter, &format!("spheres[{}]", i), sphere);
+    }
+
+    for (i, rhombohedron) in file.rhombohedrons.iter_mut().enumerate() {
+        check_rhombohedron(&mut linter, &format!("rhombohedrons[{}]", i), rhombohedron);
+    }
+
+    for (i, polygon) in file.polygons.iter_mut().enumerate() {
+        check_polygon(&mut linter, &format!("polygons[{}]", i), polygon);
+    }
+
+    for (i, ellipsoid) in file.ellipsoids.iter_mut().enumerate() {
+        check_ellipsoid(&mut linter, &format!("ellipsoids[{}]", i), ellipsoid);
+    }
+
+    check_lights(&mut linter, file);
+
+    linter.diagnostics
+}
+
+fn check_camera(linter: &mut Linter, file: &mut SceneFile) {
+    let has_legacy = file.viewport_origin.is_some()
+        || file.viewport_x_axis.is_some()
+        || file.viewport_y_axis.is_some()
+        || file.eye_position.is_some();
+
+    match file.camera {
+        Some(look_at) => {
+            if let Some((field, message)) = look_at.problem() {
+                linter.report(Severity::Error, format!("camera.{}", field), message);
+            }
+
+            if has_legacy
+                && linter.report_fixable(
+                    Severity::Warning,
+                    "camera".to_string(),
+                    "`viewport_origin`, `viewport_x_axis`, `viewport_y_axis` and `eye_position` are ignored when there's a `camera`".to_string(),
+                )
+            {
+                file.viewport_origin = None;
+                file.viewport_x_axis = None;
+                file.viewport_y_axis = None;
+                file.eye_position = None;
+            }
+        }
+        None if file.legacy_camera().is_none() => {
+            linter.report(Severity::Error, "camera".to_string(), "scene has no camera".to_string());
+        }
+        None => {}
+    }
+
+    if let Some((field, message)) = file.projection.problem() {
+        linter.report(Severity::Error, format!("projection.{}", field), message);
+    }
+
+    if let Some((field, message)) = file.stereo.and_then(|stereo| stereo.problem()) {
+        linter.report(Severity::Error, format!("stereo.{}", field), message);
+    }
+
+    if let Some((field, message)) = file.environment.as_ref().and_then(|environment| environment.problem()) {
+        linter.report(Severity::Error, format!("environment.{}", field), message);
+    }
+
+    if let Some(lens) = file.lens {
+        if let Some((field, message)) = lens.problem() {
+            linter.report(Severity::Error, format!("lens.{}", field), message);
+        } else if lens.aperture_radius > 0.0 && (file.aa_type != AntiAliasType::MonteCarlo || file.aa_rate < 2) {
+            linter.report(
+                Severity::Warning,
+                "lens".to_string(),
+                "depth of field is noisy without `MonteCarlo` anti-aliasing and an `aa_rate` above 1".to_string(),
+            );
+        }
+    }
+}
+
+fn approx_eq(a: &Float3, b: &Float3) -> bool {
+    (a - b).norm() <= TOLERANCE * (1.0 + b.norm())
+}
+
+fn check_sphere(linter: &mut Linter, path: &str, sphere: &mut Sphere) {
+    if sphere.radius <= 0.0 || sphere.radius.is_nan() {
+        linter.report(
+            Severity::Error,
+            format!("{}.radius", path),
+            format!("radius must be greater than zero, found {}", sphere.radius),
+        );
+    }
+}
+
+// Returns false if the normal can't be used at all.
+fn check_plane(linter: &mut Linter, path: &str, plane: &mut Plane) -> bool {
+    let length = plane.normal.norm();
+
+    if length == 0.0 || !length.is_finite() {
+        linter.report(Severity::Error, format!("{}.normal", path), "normal has no direction".to_string());
+        return false;
+    }
+
+    if (length - 1.0).abs() > TOLERANCE
+        && linter.report_fixable(
+            Severity::Warning,
+            format!("{}.normal", path),
+            format!("normal should be unit length, found length {}", length),
+        )
+    {
+        plane.normal /= length;
+    }
+
+    true
+}
+
+fn check_rhombohedron(linter: &mut Linter, path: &str, rhombohedron: &mut Rhombohedron) {
+    let mut usable = true;
+
+    for (i, plane) in rhombohedron.planes.iter_mut().enumerate() {
+        usable &= check_plane(linter, &format!("{}.planes[{}]", path, i), plane);
+    }
+
+    if !usable {
+        return;
+    }
+
+    if let Some((field, message)) = rhombohedron.problem() {
+        linter.report(Severity::Error, format!("{}.{}", path, field), message);
+    }
+}
+
+fn check_triangle(linter: &mut Linter, path: &str, triangle: &mut Triangle) -> bool {
+    let derived = Triangle::from_vertices(triangle.vertices);
+
+    if !derived.normal.iter().all(|x| x.is_finite()) {
+        linter.report(
+            Severity::Error,
+            format!("{}.vertices", path),
+            "vertices are in a line, the triangle has no area".to_string(),
+        );
+        return false;
+    }
+
+    for i in 0..2 {
+        if !approx_eq(&triangle.edges[i], &derived.edges[i])
+            && linter.report_fixable(
+                Severity::Error,
+                format!("{}.edges[{}]", path, i),
+                format!("should be `vertices[{}] - vertices[0]`", i + 1),
+            )
+        {
+            triangle.edges[i] = derived.edges[i];
+        }
+    }
+
+    if !approx_eq(&triangle.normal, &derived.normal)
+        && linter.report_fixable(
+            Severity::Warning,
+            format!("{}.normal", path),
+            "should be the unit normal of `edges[0]` x `edges[1]`".to_string(),
+        )
+    {
+        triangle.normal = derived.normal;
+    }
+
+    true
+}
+
+fn check_polygon(linter: &mut Linter, path: &str, polygon: &mut Polygon) {
+    let mut usable = true;
+
+    if polygon.triangles.is_empty() {
+        linter.report(Severity::Error, format!("{}.triangles", path), "polygon has no triangles".to_string());
+        return;
+    }
+
+    for (i, triangle) in polygon.triangles.iter_mut().enumerate() {
+        usable &= check_triangle(linter, &format!("{}.triangles[{}]", path, i), triangle);
+    }
+
+    usable &= check_plane(linter, &format!("{}.plane", path), &mut polygon.plane);
+
+    if !usable {
+        return;
+    }
+
+    let off_plane = |plane: &Plane, vertex: &Float3| {
+        let normal = plane.normal.normalize();
+        let scale = 1.0 + (vertex - plane.point).norm();
+
+        (vertex - plane.point).dot(&normal).abs() > TOLERANCE * scale
+    };
+
+    let first = polygon.triangles[0];
+    let derived = Plane {
+        normal: first.normal,
+        point: first.vertices[0],
+    };
+
+    let vertices = polygon.triangles.iter().flat_map(|triangle| triangle.vertices.iter());
+    let planar = vertices.clone().all(|vertex| !off_plane(&derived, vertex));
+
+    if !planar {
+        linter.report(
+            Severity::Error,
+            format!("{}.triangles", path),
+            "the triangles don't all lie in one plane".to_string(),
+        );
+        return;
+    }
+
+    // The triangles agree with each other, so if the plane doesn't it's the
+    // one that's wrong.
+    let wrong_plane = vertices.clone().any(|vertex| off_plane(&polygon.plane, vertex))
+        || !approx_eq(&polygon.plane.normal.normalize(), &derived.normal);
+
+    if wrong_plane
+        && linter.report_fixable(
+            Severity::Error,
+            format!("{}.plane", path),
+            "doesn't match the plane the triangles lie in".to_string(),
+        )
+    {
+        polygon.plane = derived;
+    }
+}
+
+fn check_ellipsoid(linter: &mut Linter, path: &str, ellipsoid: &mut Ellipsoid) {
+    let determinant = ellipsoid.inverse.determinant();
+
+    if determinant.abs() <= TOLERANCE || !determinant.is_finite() {
+        linter.report(
+            Severity::Error,
+            format!("{}.inverse", path),
+            "matrix is singular, the ellipsoid is flat or infinite".to_string(),
+        );
+        return;
+    }
+
+    let transpose = ellipsoid.inverse.transpose();
+
+    if (ellipsoid.inverse_transpose - transpose).norm() > TOLERANCE * (1.0 + transpose.norm())
+        && linter.report_fixable(
+            Severity::Error,
+            format!("{}.inverse_transpose", path),
+            "should be the transpose of `inverse`".to_string(),
+        )
+    {
+        ellipsoid.inverse_transpose = transpose;
+    }
+}
+
+// Closed shapes that contain `point` and don't let any light through, with
+// their paths.
+fn opaque_shapes_containing(file: &SceneFile, point: &Float3) -> Vec<String> {
+    let opaque = |shape: &dyn Shape| shape.material().specular_coefficient <= 0.0;
+
+    let in_sphere = |sphere: &Sphere| opaque(sphere) && (point - sphere.center).norm() < sphere.radius;
+
+    let in_rhombohedron = |rhombohedron: &Rhombohedron| {
+        opaque(rhombohedron)
+            && rhombohedron
+                .planes
+                .iter()
+                .all(|plane| (point - plane.point).dot(&plane.normal) < 0.0)
+    };
+
+    let in_ellipsoid =
+        |ellipsoid: &Ellipsoid| opaque(ellipsoid) && (ellipsoid.inverse * (point - ellipsoid.center)).norm() < 1.0;
+
+    let mut out = Vec::new();
+
+    for (i, shape) in file.shapes.iter().enumerate() {
+        let inside = match shape {
+            Primitive::Sphere(sphere) => in_sphere(sphere),
+            Primitive::Rhombohedron(rhombohedron) => in_rhombohedron(rhombohedron),
+            Primitive::Polygon(_) => false,
+            Primitive::Ellipsoid(ellipsoid) => in_ellipsoid(ellipsoid),
+        };
+
+        if inside {
+            out.push(format!("shapes[{}]", i));
+        }
+    }
+
+    out.extend(file.spheres.iter().enumerate().filter(|(_, s)| in_sphere(s)).map(|(i, _)| format!("spheres[{}]", i)));
+    out.extend(
+        file.rhombohedrons
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| in_rhombohedron(r))
+            .map(|(i, _)| format!("rhombohedrons[{}]", i)),
+    );
+    out.extend(
+        file.ellipsoids
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| in_ellipsoid(e))
+            .map(|(i, _)| format!("ellipsoids[{}]", i)),
+    );
+
+    out
+}
+
+fn check_lights(linter: &mut Linter, file: &mut SceneFile) {
+    for (i, light) in file.lights.iter_mut().enumerate() {
+        if light.shadow_samples == Some(0)
+            && linter.report_fixable(
+                Severity::Error,
+                format!("lights[{}].shadow_samples", i),
+                "must be at least 1".to_string(),
+            )
+        {
+            light.shadow_samples = Some(1);
+        }
+    }
+
+    for (i, light) in file.lights.iter().enumerate() {
+        match light.problem() {
+            // Already reported above.
+            Some(("shadow_samples", _)) | None => {}
+            Some((field, message)) => linter.report(Severity::Error, format!("lights[{}].{}", i, field), message),
+        }
+
+        // Directional lights have no center, a missing one is reported above.
+        let center = match (light.kind, light.center) {
+            (LightKind::Directional { .. }, _) | (_, None) => continue,
+            (_, Some(center)) => center,
+        };
+
+        // Shadow rays stop at the first opaque thing they hit, so nothing
+        // outside the shape is lit.
+        for shape in opaque_shapes_containing(file, &center) {
+            linter.report(
+                Severity::Warning,
+                format!("lights[{}].center", i),
+                format!("light is inside `{}` and is blocked by it", shape),
+            );
+        }
+    }
+}
+
+enum Segment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+fn parse_path(path: &str) -> Vec<Segment<'_>> {
+    let mut out = Vec::new();
+
+    for part in path.split('.').filter(|part| !part.is_empty()) {
+        let mut pieces = part.split('[');
+
+        if let Some(key) = pieces.next().filter(|key| !key.is_empty()) {
+            out.push(Segment::Key(key));
+        }
+
+        for index in pieces {
+            if let Ok(index) = index.trim_end_matches(']').parse() {
+                out.push(Segment::Index(index));
+            }
+        }
+    }
+
+    out
+}
+
+// Just enough of a json reader to find where values start.
+struct Cursor<'a> {
+    source: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.source.get(self.offset).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.offset += 1;
+        }
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        self.skip_whitespace();
+
+        if self.peek() == Some(c) {
+            self.offset += 1;
+            return true;
+        }
+
+        false
+    }
+
+    // Returns the raw contents of the string, escapes are left as is.
+    fn string(&mut self) -> Option<&'a [u8]> {
+        if !self.eat(b'"') {
+            return None;
+        }
+
+        let start = self.offset;
+
+        loop {
+            match self.peek()? {
+                b'\\' => self.offset += 2,
+                b'"' => break,
+                _ => self.offset += 1,
+            }
+        }
+
+        self.offset += 1;
+
+        self.source.get(start..self.offset - 1)
+    }
+
+    fn skip_value(&mut self) -> Option<()> {
+        self.skip_whitespace();
+
+        match self.peek()? {
+            b'"' => {
+                self.string()?;
+            }
+            b'{' | b'[' => {
+                let mut depth = 0;
+
+                loop {
+                    match self.peek()? {
+                        b'"' => {
+                            self.string()?;
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => depth -= 1,
+                        _ => {}
+                    }
+
+                    self.offset += 1;
+
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+            _ => {
+                while !matches!(self.peek(), None | Some(b',' | b'}' | b']' | b' ' | b'\t' | b'\n' | b'\r')) {
+                    self.offset += 1;
+                }
+            }
+        }
+
+        Some(())
+    }
+
+    // Moves to the start of the value at `path`, or as close as it can get.
+    fn find(&mut self, path: &[Segment]) {
+        self.skip_whitespace();
+
+        let start = self.offset;
+
+        let found = match path.first() {
+            None => return,
+            Some(Segment::Key(key)) => self.find_key(key.as_bytes()),
+            Some(Segment::Index(index)) => self.find_index(*index),
+        };
+
+        if found.is_some() {
+            self.find(&path[1..]);
+        } else {
+            self.offset = start;
+        }
+    }
+
+    fn find_key(&mut self, key: &[u8]) -> Option<()> {
+        if !self.eat(b'{') {
+            return None;
+        }
+
+        loop {
+            let name = self.string()?;
+
+            if !self.eat(b':') {
+                return None;
+            }
+
+            if name == key {
+                return Some(());
+            }
+
+            self.skip_value()?;
+
+            if !self.eat(b',') {
+                return None;
+            }
+        }
+    }
+
+    fn find_index(&mut self, index: usize) -> Option<()> {
+        if !self.eat(b'[') {
+            return None;
+        }
+
+        for _ in 0..index {
+            self.skip_value()?;
+
+            if !self.eat(b',') {
+                return None;
+            }
+        }
+
+        Some(())
+    }
+}
+
+/// Finds the line and column (both starting at 1) of the value at `path` in
+/// the json `source`. If the value isn't there, e.g. because it was left out
+/// and defaulted, this gives the closest thing containing it.
+pub fn locate(source: &str, path: &str) -> (usize, usize) {
+    let mut cursor = Cursor {
+        source: source.as_bytes(),
+        offset: 0,
+    };
+
+    cursor.find(&parse_path(path));
+
+    let before = &source.as_bytes()[..cursor.offset];
+    let line = before.iter().filter(|&&c| c == b'\n').count() + 1;
+    let line_start = before.iter().rposition(|&c| c == b'\n').map_or(0, |i| i + 1);
+
+    // Count characters rather than bytes.
+    let column = before[line_start..].iter().filter(|&&c| (c & 0xC0) != 0x80).count() + 1;
+
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"{
+  "spheres": [
+    { "center": [0, 0, 0], "radius": 1, "name": "a \"quoted\" {name}, [1]" },
+    { "radius": { "inner": 2 }, "center": [1, 2, 3], "radius": 0.5 }
+  ],
+  "lights": [
+    {
+      "kind": { "Spot": { "direction": [0, -1, 0], "inner_angle": 40, "outer_angle": 30 } },
+      "color": [1, 1, 1]
+    }
+  ],
+  "note": "ünïcode", "width": 640
+}"#;
+
+    #[test]
+    fn locates_nested_values() {
+        assert_eq!(locate(SOURCE, "spheres"), (2, 14));
+        assert_eq!(locate(SOURCE, "spheres[0].radius"), (3, 38));
+        assert_eq!(locate(SOURCE, "spheres[0].center[2]"), (3, 24));
+        assert_eq!(locate(SOURCE, "lights[0].kind.Spot.inner_angle"), (8, 67));
+        assert_eq!(locate(SOURCE, "lights[0].color"), (9, 16));
+    }
+
+    #[test]
+    fn skips_strings_and_nested_values() {
+        // A key given twice is found where it first appears.
+        assert_eq!(locate(SOURCE, "spheres[1].radius"), (4, 17));
+        assert_eq!(locate(SOURCE, "spheres[1].center"), (4, 43));
+
+        // Quotes, braces, brackets & commas inside the first sphere's name
+        // don't throw off finding the second.
+        assert_eq!(locate(SOURCE, "spheres[1]"), (4, 5));
+    }
+
+    #[test]
+    fn columns_count_characters() {
+        assert_eq!(locate(SOURCE, "width"), (12, 31));
+    }
+
+    #[test]
+    fn missing_values_give_what_contains_them() {
+        // Left out and defaulted.
+        assert_eq!(locate(SOURCE, "lights[0].radius"), (7, 5));
+        assert_eq!(locate(SOURCE, "lights[0].kind.Spot.exponent"), (8, 25));
+
+        // Out of range.
+        assert_eq!(locate(SOURCE, "spheres[5].radius"), (2, 14));
+        assert_eq!(locate(SOURCE, "camera.eye"), (1, 1));
+        assert_eq!(locate(SOURCE, ""), (1, 1));
+    }
+
+    #[test]
+    fn parses_paths() {
+        let path = parse_path("lights[0].kind.Spot.direction[2]");
+
+        assert!(matches!(
+            path.as_slice(),
+            [
+                Segment::Key("lights"),
+                Segment::Index(0),
+                Segment::Key("kind"),
+                Segment::Key("Spot"),
+                Segment::Key("direction"),
+                Segment::Index(2),
+            ]
+        ));
+
+        assert!(matches!(
+            parse_path("shapes[1][0]").as_slice(),
+            [Segment::Key("shapes"), Segment::Index(1), Segment::Index(0)]
+        ));
+    }
+}
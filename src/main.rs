@@ -1,107 +1,295 @@
 use std::env;
+use std::fs;
+use std::process;
+use std::sync::Arc;
+#[cfg(feature = "viewer")]
 use std::thread::sleep;
+#[cfg(feature = "viewer")]
 use std::time::Duration;
 
-use sdl2::event::Event;
-use sdl2::pixels::Color;
-use sdl2::rect::Point;
+use raytracer::image::{ImageBuffer, ImageFormat};
+use raytracer::lint::{self, Severity};
+use raytracer::scene::{SceneError, SceneFile};
+use raytracer::stereo::{self, Stereo};
+#[cfg(feature = "viewer")]
+use raytracer::render::{Canvas, Renderer};
+#[cfg(feature = "viewer")]
+use raytracer::viewer::Window;
+use raytracer::*;
 
+mod cli;
+use cli::{Command, LintOptions, Mode, Options};
 
-mod raytracer;
-use raytracer::render::Canvas;
-use raytracer::*;
+// Forwards every pixel to both canvases, used to keep a copy of what is
+// drawn to the window so it can be saved once rendering is done.
+#[cfg(feature = "viewer")]
+struct TeeCanvas<'a, A: Canvas, B: Canvas> {
+    first: &'a mut A,
+    second: &'a mut B,
+}
+
+#[cfg(feature = "viewer")]
+impl<A: Canvas, B: Canvas> Canvas for TeeCanvas<'_, A, B> {
+    fn set_pixel(&mut self, x: u32, y: u32, color: &Float3) {
+        self.first.set_pixel(x, y, color);
+        self.second.set_pixel(x, y, color);
+    }
 
-fn float3_to_color(input: &Float3) -> Color {
-    Color::RGB(
-        f64::max(0.0, f64::min(255.0, input.x * 255.0)) as u8,
-        f64::max(0.0, f64::min(255.0, input.y * 255.0)) as u8,
-        f64::max(0.0, f64::min(255.0, input.z * 255.0)) as u8,
-    )
+    fn present(&mut self) {
+        self.first.present();
+        self.second.present();
+    }
 }
 
-struct Window {
-    context: sdl2::Sdl,
-    canvas: sdl2::render::WindowCanvas,
-    // width: u32,
-    height: u32,
+// Prints a scene error along with the offending line of the file, so it can
+// be found without counting columns by hand.
+fn report_scene_error(filename: &str, err: &SceneError) {
+    // Parse errors already start with the line and column.
+    match err {
+        SceneError::Parse { .. } => eprintln!("error: {}:{}", filename, err),
+        _ => eprintln!("error: {}: {}", filename, err),
+    }
+
+    if let SceneError::Parse { line, column, .. } = err {
+        let source = fs::read_to_string(filename).unwrap_or_default();
+
+        if let Some(text) = source.lines().nth(line.saturating_sub(1)) {
+            let gutter = line.to_string();
+            let caret = column.saturating_sub(1);
+            let prefix: String = text.chars().take(caret).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+
+            eprintln!("{} |", " ".repeat(gutter.len()));
+            eprintln!("{} | {}", gutter, text);
+            eprintln!("{} | {}^", " ".repeat(gutter.len()), prefix);
+        }
+    }
 }
 
-impl Window {
-    pub fn new(width: u32, height: u32) -> Window {
-        let context = sdl2::init().unwrap();
-        let window = context
-            .video()
-            .unwrap()
-            .window("raytracer", width, height)
-            .position_centered()
-            .build()
-            .unwrap();
-        let mut canvas = window.into_canvas().build().unwrap();
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
-        canvas.clear();
-        canvas.present();
-
-        Window { context, canvas, /*width,*/ height }
-    }
-
-    pub fn event_pump(&self) -> bool {
-        let mut event_pump = self.context.event_pump().unwrap();
-
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. } => return false,
-                _ => {}
-            }
+// Prints every problem lint finds, returns the exit code.
+fn run_lint(options: &LintOptions) -> i32 {
+    let mut file = match SceneFile::from_file(&options.scene) {
+        Ok(file) => file,
+        Err(err) => {
+            report_scene_error(&options.scene, &err);
+            return 1;
+        }
+    };
+
+    let source = fs::read_to_string(&options.scene).unwrap_or_default();
+
+    let diagnostics = if options.fix {
+        lint::fix(&mut file)
+    } else {
+        lint::lint(&file)
+    };
+
+    for diagnostic in diagnostics.iter() {
+        let (line, column) = lint::locate(&source, &diagnostic.path);
+
+        let note = if diagnostic.fixed {
+            " [fixed]"
+        } else if diagnostic.fixable {
+            " [fixable]"
+        } else {
+            ""
+        };
+
+        println!(
+            "{}: {}:{}:{}: {} (at `{}`){}",
+            diagnostic.severity, options.scene, line, column, diagnostic.message, diagnostic.path, note
+        );
+    }
+
+    let remaining: Vec<_> = diagnostics.iter().filter(|d| !d.fixed).collect();
+    let errors = remaining.iter().filter(|d| d.severity == Severity::Error).count();
+    let warnings = remaining.len() - errors;
+    let fixed = diagnostics.len() - remaining.len();
+    let fixable = remaining.iter().filter(|d| d.fixable).count();
+
+    if fixed > 0 {
+        let output = options.output.as_ref().unwrap_or(&options.scene);
+
+        if let Err(err) = file.to_file(output) {
+            eprintln!("error: {}: {}", output, err);
+            return 1;
         }
 
-        true
+        println!("fixed {} problem(s), wrote {}", fixed, output);
     }
-}
 
-impl Canvas for Window {
-    fn set_pixel(&mut self, x: u32, y: u32, color: &Float3) {
-        let sdl_color = float3_to_color(&color);
-        self.canvas.set_draw_color(sdl_color);
+    if remaining.is_empty() {
+        if diagnostics.is_empty() {
+            println!("{}: no problems found", options.scene);
+        }
 
-        // (0,0) on the window is the top left corner.
-        // (0,0) on the renderer is the bottom left.
-        let y = self.height - y;
+        return 0;
+    }
 
-        self.canvas
-            .draw_point(Point::new(x as i32, y as i32))
-            .expect("Failed to draw point");
+    print!("{} error(s), {} warning(s)", errors, warnings);
+
+    if fixable > 0 {
+        print!(", {} can be repaired with --fix", fixable);
     }
 
-    fn present(&mut self) {
-        self.canvas.present();
+    println!();
+
+    1
+}
+
+// Writes the image to `--output` if one was given. Errors are ready to print,
+// prefixed with the file name.
+fn save_image(image: &ImageBuffer, options: &Options) -> Result<(), String> {
+    if let Some((filename, format)) = &options.output {
+        write_image(image, filename, *format)?;
     }
+
+    Ok(())
 }
 
-fn main() {
-    simple_logger::init().unwrap();
+fn write_image(image: &ImageBuffer, filename: &str, format: ImageFormat) -> Result<(), String> {
+    image
+        .to_file_with_format(filename, format)
+        .map_err(|err| format!("{}: {}", filename, err))
+}
 
-    let args: Vec<String> = env::args().collect();
+// Renders each eye in turn then writes them out in the requested layout.
+fn render_stereo(mut scene: Arc<Scene>, stereo: &Stereo, options: &Options) -> Result<(), String> {
+    let (filename, format) = options
+        .output
+        .as_ref()
+        .ok_or_else(|| "stereo rendering needs an output file, use --output".to_string())?;
+    let settings = options.render_settings();
 
-    if args.len() == 1 {
-        panic!("You must provide a filename");
+    let mut eyes = Vec::new();
+
+    for camera in stereo.cameras(&scene.camera).iter() {
+        // The previous eye's render threads have finished with the scene so
+        // this doesn't copy it.
+        Arc::make_mut(&mut scene).camera = *camera;
+
+        let mut image = ImageBuffer::new(scene.width, scene.height);
+        raytracer::render::render_scene(scene.clone(), &mut image, &settings);
+
+        eyes.push(image);
+    }
+
+    match stereo::compose(&eyes[0], &eyes[1], stereo.layout) {
+        Some(image) => write_image(&image, filename, *format),
+        None => {
+            for (image, eye) in eyes.iter().zip(["left", "right"].iter()) {
+                write_image(image, &stereo::eye_filename(filename, eye), *format)?;
+            }
+
+            Ok(())
+        }
     }
+}
 
-    let scene = Scene::from_file(&args[1]);
+// Renders the whole scene into memory without initializing SDL, then writes
+// the result to the output file.
+fn render_headless(scene: Arc<Scene>, options: &Options) -> Result<(), String> {
+    if let Some(stereo) = scene.stereo {
+        return render_stereo(scene, &stereo, options);
+    }
 
+    let mut image = ImageBuffer::new(scene.width, scene.height);
+
+    raytracer::render::render_scene(scene, &mut image, &options.render_settings());
+
+    save_image(&image, options)
+}
+
+// The window stays open after a failed save, the error is returned once it's
+// closed.
+#[cfg(feature = "viewer")]
+fn render_window(scene: Arc<Scene>, options: &Options) -> Result<(), String> {
     let mut window = Window::new(scene.width, scene.height);
+    let mut image = ImageBuffer::new(scene.width, scene.height);
 
-    let mut y = 0;
+    let mut renderer = Renderer::start(scene, &options.render_settings());
+    let mut done = false;
+    let mut result = Ok(());
 
     'running: loop {
         if !window.event_pump() {
             break 'running;
         }
 
-        if y != u32::MAX {
-            y = raytracer::render::render_scene(&scene, &mut window, y, 10);
+        if !done {
+            let mut canvas = TeeCanvas {
+                first: &mut window,
+                second: &mut image,
+            };
+
+            done = renderer.poll(&mut canvas, Duration::from_millis(16));
+
+            if done {
+                result = save_image(&image, options);
+
+                if let Err(message) = &result {
+                    eprintln!("error: {}", message);
+                }
+            }
+        } else {
+            sleep(Duration::from_millis(16));
         }
 
         window.present();
-        sleep(Duration::from_millis(1));
+    }
+
+    result
+}
+
+fn main() {
+    simple_logger::init().unwrap();
+
+    let options = match cli::parse(env::args().skip(1)) {
+        Ok(Command::Render(options)) => options,
+        Ok(Command::Lint(options)) => process::exit(run_lint(&options)),
+        Ok(Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Err(message) => {
+            eprintln!("error: {}\n\nRun 'raytracer --help' for usage.", message);
+            process::exit(2);
+        }
+    };
+
+    if options.mode == Mode::Window && !cfg!(feature = "viewer") {
+        eprintln!("error: this build has no window support, rebuild with '--features viewer' or use --output to render headless");
+        process::exit(2);
+    }
+
+    let mut scene = match Scene::from_file(&options.scene) {
+        Ok(scene) => scene,
+        Err(err) => {
+            report_scene_error(&options.scene, &err);
+            process::exit(1);
+        }
+    };
+
+    if let Err(message) = options.apply_overrides(&mut scene) {
+        eprintln!("error: {}\n\nRun 'raytracer --help' for usage.", message);
+        process::exit(2);
+    }
+
+    let scene = Arc::new(scene);
+
+    let result = match options.mode {
+        Mode::Headless => render_headless(scene, &options),
+        #[cfg(feature = "viewer")]
+        Mode::Window => render_window(scene, &options),
+        #[cfg(not(feature = "viewer"))]
+        Mode::Window => unreachable!(),
+    };
+
+    if let Err(message) = result {
+        // The window has already shown it.
+        if options.mode == Mode::Headless {
+            eprintln!("error: {}", message);
+        }
+
+        process::exit(1);
     }
 }
@@ -0,0 +1,1429 @@
+use super::filter::Filter;
+use super::emitter::Emitter;
+use super::environment::Background;
+use super::ray_vs_scene;
+use super::ray_vs_scene_shadow;
+use super::ray_vs_scene_shape;
+use super::sampler::{cosine_hemisphere, create_sampler, phong_lobe, uniform_cone, Sampler};
+use super::scene::AntiAliasType;
+use super::shapes::*;
+use super::tiles::{create_tiles, Tile, TileOrder};
+use super::Intersection;
+use super::Occlusion;
+use super::Scene;
+
+use log::info;
+use serde::{Serialize, Deserialize};
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::panic;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+
+use float_cmp::approx_eq;
+
+use super::Float3;
+
+/// How the light coming back along camera rays is worked out.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum Integrator {
+    /// Lights shine straight onto surfaces and perfect reflections and
+    /// refractions are followed, `ambient` stands in for all other light.
+    #[default]
+    Whitted,
+    /// Follows random paths that bounce off diffuse and glossy surfaces too,
+    /// so light spills from one surface onto another. `ambient` isn't used.
+    PathTracer,
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    // "path" is short for "path-tracer".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = crate::normalize_name(s);
+
+        match name.as_str() {
+            "whitted" => Ok(Integrator::Whitted),
+            "pathtracer" | "path" => Ok(Integrator::PathTracer),
+            _ => Err(format!("Unknown integrator '{}'", s)),
+        }
+    }
+}
+
+pub trait Canvas {
+    fn set_pixel(&mut self, x: u32, y: u32, color: &Float3);
+    fn present(&mut self);
+}
+
+fn get_normal(normal: Float3) -> Float3 {
+    if approx_eq!(f64, normal.dot(&normal), 1.0) {
+        return normal;
+    }
+
+    normal.normalize()
+}
+
+const EPSILON: f64 = 0.0012;
+
+// The `index`th of `count` points spread evenly over [0, 1)², each in its own
+// 1/count wide column and the rows spaced by the golden ratio, moved by
+// `shift` (wrapping around).
+fn lattice_2d(index: u32, count: u32, shift: (f64, f64)) -> (f64, f64) {
+    const INV_GOLDEN_RATIO: f64 = 0.618_033_988_749_894_9;
+
+    (
+        ((index as f64) + shift.0) / (count as f64),
+        ((index as f64) * INV_GOLDEN_RATIO + shift.1).fract(),
+    )
+}
+
+// How much of the light leaving `shadow_feeler.origin` for the end of
+// `shadow_feeler` gets there, per channel. Transparent shapes let through what
+// they'd transmit (ignoring that the ray would bend) and absorb some along the
+// way, opaque shapes block it all.
+fn shadow_transmittance(shadow_feeler: &Ray, scene: &Scene) -> Float3 {
+    match ray_vs_scene_shadow(shadow_feeler, scene) {
+        Occlusion::Clear => return Float3::new(1.0, 1.0, 1.0),
+        Occlusion::Opaque => return Float3::new(0.0, 0.0, 0.0),
+        Occlusion::Transparent => {}
+    }
+
+    let mut transmittance = Float3::new(1.0, 1.0, 1.0);
+    let mut ray = *shadow_feeler;
+    let target = shadow_feeler.origin + shadow_feeler.direction;
+    // The shapes the ray has gone into and how far along it that was.
+    // Shading only happens outside of shapes.
+    let mut inside: Vec<(usize, f64)> = Vec::new();
+
+    // Bounds how many surfaces are passed through in case a ray keeps
+    // hitting the same one.
+    for _ in 0..64 {
+        let (shape, intersection) = match ray_vs_scene_shape(&ray, scene, false, 1.0) {
+            Some(hit) => hit,
+            None => return transmittance,
+        };
+        let material = scene.shapes[shape].material();
+
+        if material.specular_coefficient <= 0.0 {
+            return Float3::new(0.0, 0.0, 0.0);
+        }
+
+        let direction = ray.direction.normalize();
+        let normal = get_normal(intersection.normal);
+        let d_dot_n = direction.dot(&normal);
+        let hit = ray.origin + (ray.direction * intersection.t);
+        let travelled = (hit - shadow_feeler.origin).norm();
+
+        // Normals point out of closed shapes so this is leaving one that was
+        // gone into, light has been absorbed since. Open shapes like polygons
+        // are only ever gone into.
+        let entered = inside.iter().position(|(index, _)| *index == shape);
+
+        let (n_i, n_t, u_i, u_t) = match entered {
+            Some(entry) if d_dot_n > 0.0 => {
+                let (_, entered_at) = inside.swap_remove(entry);
+                let distance = travelled - entered_at;
+
+                transmittance.x *= material.attenuation.x.powf(distance);
+                transmittance.y *= material.attenuation.y.powf(distance);
+                transmittance.z *= material.attenuation.z.powf(distance);
+
+                (material.index_of_refraction, 1.0, material.magnetic_permeability, 1.0)
+            }
+            _ => {
+                inside.push((shape, travelled));
+
+                (1.0, material.index_of_refraction, 1.0, material.magnetic_permeability)
+            }
+        };
+
+        transmittance *= material.specular_coefficient * (1.0 - fresnel(n_i, n_t, u_i, u_t, d_dot_n.abs()));
+
+        if transmittance.amax() < 1e-4 {
+            return Float3::new(0.0, 0.0, 0.0);
+        }
+
+        let origin = hit + (direction * EPSILON);
+
+        ray = Ray {
+            origin,
+            direction: target - origin,
+        };
+
+        // Stepped past the end of the feeler.
+        if ray.direction.dot(&shadow_feeler.direction) <= 0.0 {
+            return transmittance;
+        }
+    }
+
+    // Still going after that many is more likely a broken mesh than that
+    // much glass, don't let light leak through it.
+    Float3::new(0.0, 0.0, 0.0)
+}
+
+// Far enough that shadow rays towards a directional light get past everything
+// in the scene.
+const DIRECTIONAL_DISTANCE: f64 = 1.0e6;
+
+// From `position` to the center of `light`. Validation makes sure point and
+// spot lights have a center.
+fn to_light(light: &Light, position: Float3) -> Float3 {
+    match light.kind {
+        LightKind::Directional { direction, .. } => -direction.normalize() * DIRECTIONAL_DISTANCE,
+        _ => light.center.unwrap_or_default() - position,
+    }
+}
+
+// How bright `light` is compared to its color `distance` away, `direction`
+// points from the lit point towards the light.
+fn light_strength(light: &Light, direction: &Float3, distance: f64) -> f64 {
+    match light.kind {
+        LightKind::Directional { .. } => 1.0,
+        LightKind::Point => light.falloff.factor(distance),
+        LightKind::Spot {
+            direction: axis,
+            inner_angle,
+            outer_angle,
+            exponent,
+        } => {
+            let cos_angle = (-direction).dot(&axis.normalize());
+            let cos_inner = inner_angle.to_radians().cos();
+            let cos_outer = outer_angle.to_radians().cos();
+
+            let cone = if cos_angle >= cos_inner {
+                1.0
+            } else if cos_angle <= cos_outer {
+                0.0
+            } else {
+                ((cos_angle - cos_outer) / (cos_inner - cos_outer)).powf(exponent)
+            };
+
+            cone * light.falloff.factor(distance)
+        }
+    }
+}
+
+// How much of `light` reaches `position`, per channel. `to_light` is from
+// `to_light()`. Lights with a radius are spheres, with more than one shadow
+// sample directions are picked evenly over the cone the sphere covers as seen
+// from `position` so partly hidden lights give a penumbra. Directional lights
+// with an angle are sampled over a cone that wide.
+fn light_visibility(
+    scene: &Scene,
+    light: &Light,
+    to_light: Float3,
+    position: Float3,
+    normal: Float3,
+    sampler: &mut dyn Sampler,
+) -> Float3 {
+    let count = light.shadow_samples.unwrap_or(scene.shadow_samples).max(1);
+
+    let distance = to_light.norm();
+
+    let mut shadow_feeler = Ray {
+        // Jump slightly up from the surface so it doesn't intersect itself.
+        origin: position + (normal * EPSILON),
+        direction: to_light,
+    };
+
+    let soft = match light.kind {
+        LightKind::Directional { angle, .. } => angle > 0.0,
+        _ => !approx_eq!(f64, light.radius, 0.0) && distance > light.radius,
+    };
+
+    if count == 1 || !soft {
+        return shadow_transmittance(&shadow_feeler, scene);
+    }
+
+    let w = to_light / distance;
+
+    let cos_theta_max = match light.kind {
+        LightKind::Directional { angle, .. } => (angle.to_radians() * 0.5).cos(),
+        _ => {
+            let sin_theta_max = light.radius / distance;
+            (1.0 - (sin_theta_max * sin_theta_max)).sqrt()
+        }
+    };
+
+    // One point from the sampler shifts the whole set, so the points are
+    // spread out within this sample and move around between samples.
+    let shift = sampler.next_2d();
+
+    let mut visible = Float3::new(0.0, 0.0, 0.0);
+    for index in 0..count {
+        let (u, v) = lattice_2d(index, count, shift);
+        let direction = uniform_cone(&w, cos_theta_max, u, v);
+
+        // Only as far as the near side of the light, shadow rays stop at t = 1.
+        let length = match light.kind {
+            LightKind::Directional { .. } => distance,
+            _ => {
+                let cos_theta = direction.dot(&w);
+                let sin_theta_2 = f64::max(0.0, 1.0 - (cos_theta * cos_theta));
+                let half_chord = f64::max(0.0, (light.radius * light.radius) - (distance * distance * sin_theta_2)).sqrt();
+                (distance * cos_theta) - half_chord
+            }
+        };
+        shadow_feeler.direction = direction * length;
+
+        visible += shadow_transmittance(&shadow_feeler, scene);
+    }
+
+    visible / (count as f64)
+}
+
+// Light from `emitter` arriving at `position`, weighted by the cosine to
+// `normal` and integrated over the directions it covers. Picks
+// `shadow_samples` points on the emitter, each checked with a shadow ray.
+fn emitter_irradiance(
+    scene: &Scene,
+    emitter: &Emitter,
+    position: Float3,
+    normal: Float3,
+    sampler: &mut dyn Sampler,
+) -> Float3 {
+    let count = scene.shadow_samples.max(1);
+    let origin = position + (normal * EPSILON);
+
+    // Same as `light_visibility`.
+    let shift = sampler.next_2d();
+
+    let mut sum = Float3::new(0.0, 0.0, 0.0);
+    for index in 0..count {
+        let (u, v) = lattice_2d(index, count, shift);
+
+        let sample = match emitter.sample(&position, u, v) {
+            Some(sample) => sample,
+            None => continue,
+        };
+
+        let direction = (sample.point - position).normalize();
+        let n_dot_l = normal.dot(&direction);
+
+        if n_dot_l <= 0.0 {
+            continue;
+        }
+
+        // Stop just short so it doesn't count the emitter as in the way.
+        let shadow_feeler = Ray {
+            origin,
+            direction: sample.point - (direction * EPSILON) - origin,
+        };
+
+        let shadow = shadow_transmittance(&shadow_feeler, scene);
+        sum += shadow.component_mul(&sample.radiance) * (n_dot_l / sample.pdf);
+    }
+
+    sum / (count as f64)
+}
+
+// Same as `emitter_irradiance` for light from the environment.
+fn environment_irradiance(
+    scene: &Scene,
+    background: &Background,
+    position: Float3,
+    normal: Float3,
+    sampler: &mut dyn Sampler,
+) -> Float3 {
+    let count = scene.shadow_samples.max(1);
+    let origin = position + (normal * EPSILON);
+
+    let shift = sampler.next_2d();
+
+    let mut sum = Float3::new(0.0, 0.0, 0.0);
+    for index in 0..count {
+        let (u, v) = lattice_2d(index, count, shift);
+
+        let sample = match background.sample(&normal, u, v) {
+            Some(sample) => sample,
+            None => continue,
+        };
+
+        let n_dot_l = normal.dot(&sample.direction);
+
+        if n_dot_l <= 0.0 {
+            continue;
+        }
+
+        let shadow_feeler = Ray {
+            origin,
+            direction: sample.direction * DIRECTIONAL_DISTANCE,
+        };
+
+        let shadow = shadow_transmittance(&shadow_feeler, scene);
+        sum += shadow.component_mul(&sample.radiance) * (n_dot_l / sample.pdf);
+    }
+
+    sum / (count as f64)
+}
+
+fn local_illumination(
+    ray: &Ray,
+    scene: &Scene,
+    intersection: &Intersection,
+    material: &Material,
+    specular: f64,
+    sampler: &mut dyn Sampler,
+) -> Float3 {
+    let normal = get_normal(intersection.normal);
+    let position = intersection.t * ray.direction + ray.origin;
+
+    let mut out = Float3::new(0.0, 0.0, 0.0);
+
+    // A sky's sun is lit like any other light.
+    let sun = scene.environment.as_ref().and_then(|background| background.sun());
+
+    for light in scene.lights.iter().chain(sun) {
+        let light_vector = to_light(light, position);
+        let light_direction = light_vector.normalize();
+
+        let strength = light_strength(light, &light_direction, light_vector.norm());
+
+        // Outside a spotlight's cone, no need for shadow rays.
+        if strength <= 0.0 {
+            continue;
+        }
+
+        let light_color = light.color * strength;
+
+        // Determine if the point of intersection is in shadow
+        let shadow = light_visibility(scene, light, light_vector, position, normal, sampler);
+
+        // Diffuse Light
+        let n_dot_l = f64::max(0.0, normal.dot(&light_direction));
+        let diffuse_factor = shadow * n_dot_l;
+        out.x += diffuse_factor.x * material.diffuse.x * light_color.x;
+        out.y += diffuse_factor.y * material.diffuse.y * light_color.y;
+        out.z += diffuse_factor.z * material.diffuse.z * light_color.z;
+
+        // Specular Light
+        let l = (2.0 * normal.dot(&light_direction) * normal) - light_direction;
+        let v_dot_l = ray.direction.dot(&-l);
+        if v_dot_l > 0.0 {
+            out += v_dot_l.powf(material.specular_power) * specular * light_color;
+        }
+    }
+
+    // Emissive shapes light diffuse surfaces directly, their highlights are
+    // left to reflection rays which see the shapes themselves.
+    for emitter in scene.emitters.iter() {
+        let irradiance = emitter_irradiance(scene, emitter, position, normal, sampler);
+
+        out += material.diffuse.component_mul(&irradiance) / std::f64::consts::PI;
+    }
+
+    if let Some(background) = scene.environment.as_ref() {
+        let irradiance = environment_irradiance(scene, background, position, normal, sampler);
+
+        out += material.diffuse.component_mul(&irradiance) / std::f64::consts::PI;
+    }
+
+    out
+}
+
+fn reflect(reflection_vector: &Float3, reflected: &Float3) -> Float3 {
+    let r_dot_rv = 2.0 * reflected.dot(reflection_vector);
+
+    (reflected - (r_dot_rv * reflection_vector)).normalize()
+}
+
+fn transmit(nit: f64, normal: &Float3, from: &Float3) -> Option<Float3> {
+    let f_dot_n = from.dot(normal);
+    let cos_t = 1.0 - (nit * nit) * (1.0 - (f_dot_n * f_dot_n));
+
+    if cos_t <= 0.0 {
+        return None;
+    }
+
+    let inv = if f_dot_n < 0.0 { 1.0 } else { -1.0 };
+    let transmission = cos_t.sqrt() * inv;
+
+    Some((((transmission + (nit * f_dot_n)) * normal) - (nit * from)).normalize())
+}
+
+/// https://en.wikipedia.org/wiki/Fresnel_equations#Power_(intensity)_reflection_and_transmission_coefficients
+/// Arguments:
+/// `n_i`: The index of refraction of the transmission medium of the ray.
+/// `n_t`: The index of refraction of the object material to transmit into.
+/// `u_i`: The magnetic permeability of the transmission medium
+/// `u_t`: The magnetic permeability of the object material to transmit into.
+/// `cos_theta_i`: cos(θ_i) where θ_i is the angle of incidence
+fn fresnel(n_i: f64, n_t: f64, u_i: f64, u_t: f64, cos_theta_i: f64) -> f64 {
+    let nit = n_i / n_t;
+    let uit = u_i / u_t;
+
+    let determinate = 1.0 - ((nit * nit) * (1.0 - (cos_theta_i * cos_theta_i)));
+
+    if determinate < 0.0 {
+        return 1.0;
+    }
+
+    // θ_t Angle of transmission
+    let cos_theta_t = determinate.sqrt();
+
+    let e_r_perp = (nit * cos_theta_i) - (uit * cos_theta_t);
+    let e_i_perp = (nit * cos_theta_i) + (uit * cos_theta_t);
+
+    let e_r_par = (uit * cos_theta_i) - (nit * cos_theta_t);
+    let e_i_par = (uit * cos_theta_i) + (nit * cos_theta_t);
+
+    let e_perp = e_r_perp / e_i_perp;
+    let e_par = e_r_par / e_i_par;
+
+    0.5 * ((e_perp * e_perp) + (e_par * e_par))
+}
+
+fn cast_ray(ray: &Ray, scene: &Scene, depth: u32, n_i: f64, sampler: &mut dyn Sampler) -> Float3 {
+    let mut color = Float3::new(0.0, 0.0, 0.0);
+
+    if depth == 0 {
+        return color;
+    }
+
+    let res = ray_vs_scene(ray, scene);
+
+    if res.is_none() {
+        if let Some(background) = scene.environment.as_ref() {
+            return background.radiance(&ray.direction.normalize());
+        }
+
+        return color;
+    }
+
+    let (intersection, material) = res.unwrap();
+
+    let mut n_t = 1.0;
+    let mut u_i = 1.0; // μ_i in fresnel equation
+    let mut u_t = 1.0; // μ_t in fresnel equation
+    let mut attenuation = scene.air_attenuation;
+
+    if approx_eq!(f64, n_i, 1.0) {
+        n_t = material.index_of_refraction;
+        u_t = material.magnetic_permeability;
+    } else {
+        u_i = material.magnetic_permeability;
+        attenuation = material.attenuation;
+    }
+
+    let r_dot_n = ray.direction.dot(&intersection.normal).abs();
+    let r_ = fresnel(n_i, n_t, u_i, u_t, r_dot_n);
+    let transmission_coefficient = material.specular_coefficient * (1.0 - r_);
+    let reflection_coefficient = material.specular_coefficient * r_;
+
+    let normal = intersection.normal;
+
+    if approx_eq!(f64, n_i, 1.0) {
+        color += material.emission;
+        color += scene.ambient;
+        color += local_illumination(ray, scene, &intersection, &material, reflection_coefficient, sampler);
+    }
+
+    if depth > 1 {
+        if !approx_eq!(f64, reflection_coefficient, 0.0) {
+            let normal_fudge_factor = if n_i == 1.0 { EPSILON } else { -EPSILON };
+            let point = ray.origin + (ray.direction * intersection.t) + (normal * normal_fudge_factor);
+
+            let reflection = Ray {
+                origin: point,
+                direction: reflect(&normal, &ray.direction),
+            };
+            color += reflection_coefficient * cast_ray(&reflection, scene, depth - 1, n_i, sampler);
+        }
+
+        if !approx_eq!(f64, transmission_coefficient, 0.0) {
+            if let Some(direction) = transmit(n_i / n_t, &normal, &-ray.direction) {
+                let normal_fudge_factor = if n_i == 1.0 { -EPSILON } else { EPSILON };
+                let point = ray.origin + (ray.direction * intersection.t) + (normal * normal_fudge_factor);
+
+                let transmission = Ray {
+                    origin: point,
+                    direction,
+                };
+
+                color += transmission_coefficient * cast_ray(&transmission, scene, depth - 1, n_t, sampler);
+            }
+        }
+    }
+
+    color.x *= attenuation.x.powf(intersection.t);
+    color.y *= attenuation.y.powf(intersection.t);
+    color.z *= attenuation.z.powf(intersection.t);
+
+    color
+}
+
+// Follows a single path from `ray`, picking one way to carry on at each
+// surface: a cosine weighted diffuse bounce, a reflection spread out by the
+// material's `specular_power` or a refraction, in proportion to how much each
+// of them passes on. Direct light is gathered at every surface outside of
+// shapes by `local_illumination`, so emitters and the environment only count
+// when a path gets to them by reflecting or refracting. Emissive ellipsoids
+// aren't emitters and count whichever way they're reached.
+fn trace_path(ray: &Ray, scene: &Scene, max_depth: u32, sampler: &mut dyn Sampler) -> Float3 {
+    let mut color = Float3::new(0.0, 0.0, 0.0);
+    let mut throughput = Float3::new(1.0, 1.0, 1.0);
+
+    let mut ray = *ray;
+    let mut n_i = 1.0;
+    // Whether the last bounce was a reflection or refraction, camera rays
+    // count as one.
+    let mut specular = true;
+
+    for bounce in 0..max_depth {
+        let (shape, intersection) = match ray_vs_scene_shape(&ray, scene, false, f64::MAX) {
+            Some(hit) => hit,
+            None => {
+                if let (true, Some(background)) = (specular, scene.environment.as_ref()) {
+                    color += throughput.component_mul(&background.radiance(&ray.direction.normalize()));
+                }
+
+                break;
+            }
+        };
+
+        let material = *scene.shapes[shape].material();
+
+        // Same as `cast_ray`.
+        let outside = approx_eq!(f64, n_i, 1.0);
+        let (n_t, u_i, u_t, attenuation) = if outside {
+            (material.index_of_refraction, 1.0, material.magnetic_permeability, scene.air_attenuation)
+        } else {
+            (1.0, material.magnetic_permeability, 1.0, material.attenuation)
+        };
+
+        throughput = throughput.component_mul(&attenuation.map(|a| a.powf(intersection.t)));
+
+        let r_dot_n = ray.direction.dot(&intersection.normal).abs();
+        let r_ = fresnel(n_i, n_t, u_i, u_t, r_dot_n);
+        let transmission_coefficient = material.specular_coefficient * (1.0 - r_);
+        let reflection_coefficient = material.specular_coefficient * r_;
+
+        let normal = intersection.normal;
+        let point = ray.origin + (ray.direction * intersection.t);
+
+        let mut diffuse_weight = 0.0;
+
+        if outside {
+            // Emitters have been sampled by `local_illumination` at the last
+            // surface, shapes that can't be are only found by bouncing off it.
+            if specular || !scene.emitters.iter().any(|emitter| emitter.shape == shape) {
+                color += throughput.component_mul(&material.emission);
+            }
+
+            let direct = local_illumination(&ray, scene, &intersection, &material, reflection_coefficient, sampler);
+            color += throughput.component_mul(&direct);
+
+            diffuse_weight = material.diffuse.max();
+        }
+
+        let total = diffuse_weight + reflection_coefficient + transmission_coefficient;
+
+        if total <= 0.0 || bounce + 1 == max_depth {
+            break;
+        }
+
+        let (u, v) = sampler.next_2d();
+        // The two halves of a point, so whether the path ends isn't tied to
+        // which way it went.
+        let (pick, roulette) = sampler.next_2d();
+        let pick = pick * total;
+
+        // The side of the surface the ray came from.
+        let side = if outside { normal } else { -normal };
+
+        if pick < diffuse_weight {
+            let facing = if normal.dot(&ray.direction) > 0.0 { -normal } else { normal };
+
+            throughput = throughput.component_mul(&material.diffuse) * (total / diffuse_weight);
+            ray = Ray {
+                origin: point + (facing * EPSILON),
+                direction: cosine_hemisphere(&facing, u, v),
+            };
+            specular = false;
+        } else if pick < diffuse_weight + reflection_coefficient {
+            let mirror = reflect(&normal, &ray.direction);
+            let direction = phong_lobe(&mirror, material.specular_power, u, v);
+
+            // Spread out below the surface, it's lost.
+            if direction.dot(&side) <= 0.0 {
+                break;
+            }
+
+            throughput *= total;
+            ray = Ray {
+                origin: point + (side * EPSILON),
+                direction,
+            };
+            specular = true;
+        } else {
+            let direction = match transmit(n_i / n_t, &normal, &-ray.direction) {
+                Some(direction) => direction,
+                None => break,
+            };
+
+            throughput *= total;
+            ray = Ray {
+                origin: point - (side * EPSILON),
+                direction,
+            };
+            n_i = n_t;
+            specular = true;
+        }
+
+        // Russian roulette, after a few bounces paths carrying little light
+        // are likely to end and the ones that carry on make up for them.
+        if bounce >= 2 {
+            let survive = throughput.max().min(0.95);
+
+            if roulette >= survive {
+                break;
+            }
+
+            throughput /= survive;
+        }
+    }
+
+    color
+}
+
+// Light coming back along `ray` from the scene, using its integrator.
+fn radiance(ray: &Ray, scene: &Scene, max_depth: u32, sampler: &mut dyn Sampler) -> Float3 {
+    match scene.integrator {
+        Integrator::Whitted => cast_ray(ray, scene, max_depth, 1.0, sampler),
+        Integrator::PathTracer => trace_path(ray, scene, max_depth, sampler),
+    }
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    (a * (1.0 - t)) + (b * t)
+}
+
+fn samples_per_pixel(scene: &Scene) -> u32 {
+    match scene.aa_type {
+        _ if scene.aa_rate == 1 => 1,
+        AntiAliasType::None => 1,
+        AntiAliasType::SuperSample | AntiAliasType::MonteCarlo => (scene.aa_rate as u32) * (scene.aa_rate as u32),
+        // Each point of the finest grid is sampled at most once.
+        AntiAliasType::Adaptive => adaptive_grid_side(scene) * adaptive_grid_side(scene),
+    }
+}
+
+// The primary ray for sample `index` of a pixel, along with where in the pixel
+// it is relative to the center. Rays that fall outside the image, e.g. past the
+// edge of a fisheye's image circle, are `None`.
+fn camera_ray(scene: &Scene, x: u32, y: u32, index: u32, sampler: &mut dyn Sampler) -> ((f64, f64), Option<Ray>) {
+    let dx = 2.0 / (scene.width as f64);
+    let dy = 2.0 / (scene.height as f64);
+
+    let min_x = -1.0 + ((x as f64) - 0.5) * dx;
+    let min_y = -1.0 + ((y as f64) - 0.5) * dy;
+    let max_x = -1.0 + ((x as f64) + 0.5) * dx;
+    let max_y = -1.0 + ((y as f64) + 0.5) * dy;
+
+    let aa_type = if 1 == scene.aa_rate {
+        AntiAliasType::None
+    } else {
+        scene.aa_type
+    };
+
+    // Always drawn so the lens is the second dimension whatever the
+    // anti-aliasing type.
+    let pixel_sample = sampler.next_2d();
+
+    let (x, y, offset) = match aa_type {
+        AntiAliasType::None => (-1.0 + (x as f64) * dx, -1.0 + (y as f64) * dy, (0.0, 0.0)),
+        AntiAliasType::SuperSample => {
+            let rate = scene.aa_rate as u32;
+            let (u, v) = (((index / rate) as f64) / (rate as f64), ((index % rate) as f64) / (rate as f64));
+
+            (lerp(min_x, max_x, u), lerp(min_y, max_y, v), (u - 0.5, v - 0.5))
+        }
+        AntiAliasType::MonteCarlo => (
+            lerp(min_x, max_x, pixel_sample.0),
+            lerp(min_y, max_y, pixel_sample.1),
+            (pixel_sample.0 - 0.5, pixel_sample.1 - 0.5),
+        ),
+        // Handled by `adaptive_pixel_color` as it needs to see the colors.
+        AntiAliasType::Adaptive => unreachable!(),
+    };
+
+    (offset, scene.camera.ray(x, y, sampler.next_2d()))
+}
+
+#[derive(Debug, Copy, Clone)]
+struct AdaptiveSample {
+    color: Float3,
+    // Index of the shape the primary ray hit and how far away it was.
+    shape: Option<usize>,
+    t: f64,
+}
+
+// How far apart, relative to the nearer one, two samples of the same shape can
+// be before they count as different.
+const ADAPTIVE_DEPTH_TOLERANCE: f64 = 0.05;
+
+// Two samples differ if they see different shapes, the same shape at quite
+// different depths (e.g. a sphere's silhouette against itself) or colors more
+// than `threshold` apart.
+fn samples_differ(a: &AdaptiveSample, b: &AdaptiveSample, threshold: f64) -> bool {
+    if a.shape != b.shape {
+        return true;
+    }
+
+    if a.shape.is_some() && (a.t - b.t).abs() > ADAPTIVE_DEPTH_TOLERANCE * f64::min(a.t, b.t) {
+        return true;
+    }
+
+    (a.color - b.color).amax() > threshold
+}
+
+struct AdaptivePixel<'a> {
+    scene: &'a Scene,
+    sampler: &'a mut dyn Sampler,
+    max_depth: u32,
+    // Corners of the pixel.
+    min: (f64, f64),
+    max: (f64, f64),
+    // Points along each side of the finest grid the pixel can be split into.
+    side: u32,
+    // Samples taken so far by where they are on that grid, neighbouring
+    // regions share their edges and corners so each point is sampled once.
+    samples: Vec<Option<AdaptiveSample>>,
+    count: u32,
+}
+
+impl AdaptivePixel<'_> {
+    fn sample(&mut self, i: u32, j: u32) -> AdaptiveSample {
+        let slot = ((j * self.side) + i) as usize;
+
+        if let Some(sample) = self.samples[slot] {
+            return sample;
+        }
+
+        let steps = (self.side - 1) as f64;
+        let x = lerp(self.min.0, self.max.0, (i as f64) / steps);
+        let y = lerp(self.min.1, self.max.1, (j as f64) / steps);
+
+        self.sampler.start_sample(self.count);
+        self.count += 1;
+
+        // Keep the lens in the same dimension as other anti-aliasing types.
+        self.sampler.next_2d();
+
+        let sample = match self.scene.camera.ray(x, y, self.sampler.next_2d()) {
+            Some(ray) => {
+                let hit = ray_vs_scene_shape(&ray, self.scene, false, f64::MAX);
+
+                AdaptiveSample {
+                    color: radiance(&ray, self.scene, self.max_depth, self.sampler),
+                    shape: hit.map(|(index, _)| index),
+                    t: hit.map_or(0.0, |(_, intersection)| intersection.t),
+                }
+            }
+            None => AdaptiveSample {
+                color: Float3::new(0.0, 0.0, 0.0),
+                shape: None,
+                t: 0.0,
+            },
+        };
+
+        self.samples[slot] = Some(sample);
+
+        sample
+    }
+
+    // The square `size` grid steps across with its bottom left corner at
+    // (`i`, `j`).
+    fn region(&mut self, i: u32, j: u32, size: u32) -> Float3 {
+        let threshold = self.scene.aa_threshold;
+
+        // Bottom left, bottom right, top left, top right.
+        let corners = [
+            self.sample(i, j),
+            self.sample(i + size, j),
+            self.sample(i, j + size),
+            self.sample(i + size, j + size),
+        ];
+
+        let differ = size > 1
+            && (samples_differ(&corners[0], &corners[1], threshold)
+                || samples_differ(&corners[0], &corners[2], threshold)
+                || samples_differ(&corners[1], &corners[3], threshold)
+                || samples_differ(&corners[2], &corners[3], threshold)
+                || samples_differ(&corners[0], &corners[3], threshold));
+
+        if !differ {
+            return corners.iter().map(|sample| sample.color).sum::<Float3>() / 4.0;
+        }
+
+        let half = size / 2;
+
+        let quadrants = [
+            self.region(i, j, half),
+            self.region(i + half, j, half),
+            self.region(i, j + half, half),
+            self.region(i + half, j + half, half),
+        ];
+
+        quadrants.iter().sum::<Float3>() / 4.0
+    }
+}
+
+// Regions are halved, so they're split until they're `aa_rate` rounded up to
+// a power of two across a pixel and it never does worse than super sampling
+// at the same rate. This many points along each side of the pixel can be
+// sampled, e.g. an `aa_rate` of 3 splits into quarters on a 5x5 grid.
+fn adaptive_grid_side(scene: &Scene) -> u32 {
+    let levels = (scene.aa_rate as f64).log2().ceil() as u32;
+
+    (1 << levels) + 1
+}
+
+fn adaptive_pixel<'a>(
+    scene: &'a Scene,
+    x: u32,
+    y: u32,
+    max_depth: u32,
+    sampler: &'a mut dyn Sampler,
+) -> AdaptivePixel<'a> {
+    let dx = 2.0 / (scene.width as f64);
+    let dy = 2.0 / (scene.height as f64);
+
+    let side = adaptive_grid_side(scene);
+
+    AdaptivePixel {
+        scene,
+        sampler,
+        max_depth,
+        min: (-1.0 + ((x as f64) - 0.5) * dx, -1.0 + ((y as f64) - 0.5) * dy),
+        max: (-1.0 + ((x as f64) + 0.5) * dx, -1.0 + ((y as f64) + 0.5) * dy),
+        side,
+        samples: vec![None; (side * side) as usize],
+        count: 0,
+    }
+}
+
+fn adaptive_pixel_color(scene: &Scene, x: u32, y: u32, max_depth: u32, sampler: &mut dyn Sampler) -> Float3 {
+    let mut pixel = adaptive_pixel(scene, x, y, max_depth, sampler);
+    let side = pixel.side;
+
+    pixel.region(0, 0, side - 1)
+}
+
+// Hands each of the pixel's samples to `add` along with its offset from the
+// pixel's center. Adaptive anti-aliasing gives a single sample in the center
+// as it has already combined them.
+fn sample_pixel(
+    scene: &Scene,
+    x: u32,
+    y: u32,
+    max_depth: u32,
+    sampler: &mut dyn Sampler,
+    add: &mut dyn FnMut((f64, f64), Float3),
+) {
+    sampler.start_pixel(x, y);
+
+    if scene.aa_type == AntiAliasType::Adaptive && scene.aa_rate > 1 {
+        add((0.0, 0.0), adaptive_pixel_color(scene, x, y, max_depth, sampler));
+        return;
+    }
+
+    for index in 0..samples_per_pixel(scene) {
+        sampler.start_sample(index);
+
+        let color = match camera_ray(scene, x, y, index, sampler) {
+            (offset, Some(ray)) => (offset, radiance(&ray, scene, max_depth, sampler)),
+            (offset, None) => (offset, Float3::new(0.0, 0.0, 0.0)),
+        };
+
+        add(color.0, color.1);
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct RenderSettings {
+    pub max_depth: u32,
+    pub threads: usize,
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            max_depth: 10,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            tile_size: 32,
+            tile_order: TileOrder::Spiral,
+        }
+    }
+}
+
+fn render_tile(scene: &Scene, tile: &mut Tile, max_depth: u32, cancel: &AtomicBool) -> bool {
+    let filter = Filter::new(scene.filter, scene.filter_radius);
+    let margin = filter.margin();
+
+    // Weighted sum of the samples and the sum of the weights for each pixel.
+    let mut sums = vec![(Float3::new(0.0, 0.0, 0.0), 0.0); (tile.width * tile.height) as usize];
+
+    let mut sampler = create_sampler(scene.sampler, samples_per_pixel(scene), scene.seed);
+
+    // Wide filters need the samples of pixels around the tile as well. They
+    // come out the same as when the neighbouring tile takes them since every
+    // pixel has its own random numbers.
+    let (min_x, max_x) = (tile.x.saturating_sub(margin), u32::min(tile.x + tile.width + margin, scene.width));
+    let (min_y, max_y) = (tile.y.saturating_sub(margin), u32::min(tile.y + tile.height + margin, scene.height));
+
+    for y in min_y..max_y {
+        if cancel.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        for x in min_x..max_x {
+            // The pixels of the tile this one's samples can reach.
+            let targets_x = u32::max(x.saturating_sub(margin), tile.x)..u32::min(x + margin + 1, tile.x + tile.width);
+            let targets_y = u32::max(y.saturating_sub(margin), tile.y)..u32::min(y + margin + 1, tile.y + tile.height);
+
+            sample_pixel(scene, x, y, max_depth, sampler.as_mut(), &mut |offset, color| {
+                for target_y in targets_y.clone() {
+                    for target_x in targets_x.clone() {
+                        let weight = filter.weight(
+                            (x as f64) + offset.0 - (target_x as f64),
+                            (y as f64) + offset.1 - (target_y as f64),
+                        );
+
+                        if weight != 0.0 {
+                            let sum = &mut sums[((target_y - tile.y) * tile.width + (target_x - tile.x)) as usize];
+                            sum.0 += weight * color;
+                            sum.1 += weight;
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    tile.pixels = sums
+        .iter()
+        .map(|&(color, weight)| {
+            // Filters with negative lobes can cancel out entirely.
+            if weight.abs() < 1e-9 {
+                Float3::new(0.0, 0.0, 0.0)
+            } else {
+                color / weight
+            }
+        })
+        .collect();
+
+    true
+}
+
+/// Renders a scene in tiles on a pool of worker threads.
+///
+/// Tiles are pulled from a shared queue so a thread that finishes early
+/// immediately starts on the next one. Finished tiles are handed back to the
+/// owner of the `Renderer` through `poll` so they can be drawn to a `Canvas`
+/// (which doesn't need to be `Send`) as they complete.
+pub struct Renderer {
+    receiver: Receiver<Tile>,
+    workers: Vec<JoinHandle<()>>,
+    cancel: Arc<AtomicBool>,
+
+    tile_count: usize,
+    tiles_done: usize,
+    start_time: Instant,
+}
+
+impl Renderer {
+    pub fn start(scene: Arc<Scene>, settings: &RenderSettings) -> Self {
+        let tiles = Arc::new(create_tiles(
+            scene.width,
+            scene.height,
+            settings.tile_size,
+            settings.tile_order,
+        ));
+        let next_tile = Arc::new(AtomicUsize::new(0));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = channel();
+
+        let tile_count = tiles.len();
+        let max_depth = settings.max_depth;
+
+        let workers = (0..usize::max(1, settings.threads))
+            .map(|_| {
+                let scene = scene.clone();
+                let tiles = tiles.clone();
+                let next_tile = next_tile.clone();
+                let cancel = cancel.clone();
+                let sender = sender.clone();
+
+                thread::spawn(move || loop {
+                    let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                    if index >= tiles.len() {
+                        return;
+                    }
+
+                    let mut tile = tiles[index].clone();
+                    if !render_tile(&scene, &mut tile, max_depth, &cancel) {
+                        return;
+                    }
+
+                    if sender.send(tile).is_err() {
+                        return;
+                    }
+                })
+            })
+            .collect();
+
+        Renderer {
+            receiver,
+            workers,
+            cancel,
+            tile_count,
+            tiles_done: 0,
+            start_time: Instant::now(),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.tiles_done == self.tile_count
+    }
+
+    /// Draws every tile finished since the last call to the canvas, waiting up
+    /// to `timeout` for at least one. Returns true once the image is complete.
+    pub fn poll<T: Canvas>(&mut self, canvas: &mut T, timeout: Duration) -> bool {
+        if self.is_done() {
+            return true;
+        }
+
+        let mut tile = match self.receiver.recv_timeout(timeout) {
+            Ok(tile) => Some(tile),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => {
+                // Every worker stopped before the image was done, which only
+                // happens if one panicked. Carry on with its panic rather than
+                // one that says less.
+                for worker in self.workers.drain(..) {
+                    if let Err(payload) = worker.join() {
+                        panic::resume_unwind(payload);
+                    }
+                }
+
+                unreachable!("render threads stopped without finishing or panicking");
+            }
+        };
+
+        while let Some(finished) = tile {
+            let mut pixels = finished.pixels.iter();
+            for y in finished.y..(finished.y + finished.height) {
+                for x in finished.x..(finished.x + finished.width) {
+                    canvas.set_pixel(x, y, pixels.next().unwrap());
+                }
+            }
+
+            let previous_percent = (self.tiles_done * 10) / self.tile_count;
+            self.tiles_done += 1;
+            let percent = (self.tiles_done * 10) / self.tile_count;
+
+            if percent != previous_percent && !self.is_done() {
+                info!("{}%", percent * 10);
+            }
+
+            tile = self.receiver.try_recv().ok();
+        }
+
+        canvas.present();
+
+        if self.is_done() {
+            info!("DONE! ({:.2?})", self.start_time.elapsed());
+        }
+
+        self.is_done()
+    }
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Renders the whole scene to `canvas`, blocking until it's done.
+pub fn render_scene<T: Canvas>(scene: Arc<Scene>, canvas: &mut T, settings: &RenderSettings) {
+    let mut renderer = Renderer::start(scene, settings);
+
+    while !renderer.poll(canvas, Duration::from_millis(100)) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::Environment;
+    use crate::filter::FilterType;
+    use crate::sampler::SamplerType;
+    use crate::scene::SceneFile;
+
+    use std::path::Path;
+
+    fn material(specular_coefficient: f64, attenuation: f64) -> Material {
+        Material {
+            diffuse: Float3::new(1.0, 1.0, 1.0),
+            specular_coefficient,
+            specular_power: 1.0,
+            attenuation: Float3::new(attenuation, attenuation, attenuation),
+            electric_permittivity: 2.25,
+            magnetic_permeability: 1.0,
+            index_of_refraction: 1.5,
+            emission: Float3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn sphere(center: Float3, radius: f64, material: Material) -> Primitive {
+        Primitive::Sphere(Sphere {
+            center,
+            radius,
+            material,
+        })
+    }
+
+    fn scene(shapes: Vec<Primitive>) -> Scene {
+        let mut file: SceneFile = serde_json::from_str(
+            r#"{
+                "ambient": [0, 0, 0], "air_attenuation": [1, 1, 1],
+                "aa_type": "None", "aa_rate": 1, "width": 4, "height": 4
+            }"#,
+        )
+        .unwrap();
+        file.shapes = shapes;
+
+        Scene::from(file)
+    }
+
+    // From 2 in front of the origin to 2 behind it.
+    fn feeler() -> Ray {
+        Ray {
+            origin: Float3::new(0.0, 0.0, 2.0),
+            direction: Float3::new(0.0, 0.0, -4.0),
+        }
+    }
+
+    #[test]
+    fn shadows_pass_through_glass() {
+        let origin = Float3::new(0.0, 0.0, 0.0);
+        let clear = shadow_transmittance(&feeler(), &scene(vec![sphere(origin, 0.5, material(0.8, 1.0))]));
+        let tinted = shadow_transmittance(&feeler(), &scene(vec![sphere(origin, 0.5, material(0.8, 0.5))]));
+
+        for i in 0..3 {
+            assert!(clear[i] > 0.0 && clear[i] < 1.0, "{}", clear);
+        }
+
+        // Only the 1 unit inside the sphere absorbs anything.
+        assert!((tinted.x / clear.x - 0.5).abs() < 1e-9, "{} vs {}", tinted, clear);
+    }
+
+    #[test]
+    fn opaque_shapes_block_shadows() {
+        let origin = Float3::new(0.0, 0.0, 0.0);
+        let behind_glass = Float3::new(0.0, 0.0, -1.0);
+
+        let opaque = scene(vec![sphere(origin, 0.5, material(0.0, 1.0))]);
+        assert_eq!(shadow_transmittance(&feeler(), &opaque), Float3::new(0.0, 0.0, 0.0));
+
+        let both = scene(vec![
+            sphere(origin, 0.5, material(0.8, 0.5)),
+            sphere(behind_glass, 0.25, material(0.0, 1.0)),
+        ]);
+        assert_eq!(shadow_transmittance(&feeler(), &both), Float3::new(0.0, 0.0, 0.0));
+
+        assert_eq!(shadow_transmittance(&feeler(), &scene(vec![])), Float3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn entering_and_leaving_is_tracked_per_shape() {
+        // Faces along the feeler so going through it looks like leaving.
+        let pane = || {
+            // Off center so the feeler misses the diagonal between the
+            // triangles.
+            let corners = [(-0.3, 0.3), (0.3, 0.3), (0.3, -0.2), (-0.3, -0.2)];
+            let vertices = corners.iter().map(|&(x, y)| Float3::new(x, y, 0.0)).collect();
+
+            Primitive::Polygon(Polygon::from_vertices(vertices, material(0.8, 1.0)))
+        };
+        let glass = || sphere(Float3::new(0.0, 0.0, 0.0), 0.5, material(0.8, 0.5));
+
+        let pane_alone = shadow_transmittance(&feeler(), &scene(vec![pane()]));
+        let glass_alone = shadow_transmittance(&feeler(), &scene(vec![glass()]));
+        let both = shadow_transmittance(&feeler(), &scene(vec![glass(), pane()]));
+
+        assert!(pane_alone.x > 0.0 && pane_alone.x < 1.0, "{}", pane_alone);
+
+        // The pane isn't taken as the way out of the sphere, which still
+        // absorbs all the way through.
+        let expected = pane_alone.x * glass_alone.x;
+        assert!((both.x - expected).abs() < 1e-12, "{} instead of {}", both.x, expected);
+    }
+
+    // The mean of `count` paths traced from `origin` along `direction`.
+    fn mean_radiance(scene: &Scene, origin: Float3, direction: Float3, count: u32) -> Float3 {
+        let ray = Ray { origin, direction };
+        let mut sampler = create_sampler(SamplerType::Random, count, 1);
+        let mut sum = Float3::new(0.0, 0.0, 0.0);
+
+        sampler.start_pixel(0, 0);
+        for index in 0..count {
+            sampler.start_sample(index);
+            sum += trace_path(&ray, scene, 100, sampler.as_mut());
+        }
+
+        sum / (count as f64)
+    }
+
+    #[test]
+    fn white_furnace() {
+        let environment = Environment::Constant(Float3::new(0.5, 0.5, 0.5));
+        let white = Material {
+            diffuse: Float3::new(1.0, 1.0, 1.0),
+            ..material(0.0, 1.0)
+        };
+
+        let mut scene = scene(vec![sphere(Float3::new(0.0, 0.0, 0.0), 1.0, white)]);
+        scene.environment = Some(Arc::new(Background::new(&environment, Path::new("")).unwrap()));
+        scene.integrator = Integrator::PathTracer;
+
+        // A white surface sends back everything that lands on it, so it
+        // can't be told apart from the environment.
+        let origin = Float3::new(0.3, 0.2, 3.0);
+        let seen = mean_radiance(&scene, origin, Float3::new(0.0, 0.0, 0.0) - origin, 4000);
+        assert!((seen - Float3::new(0.5, 0.5, 0.5)).amax() < 0.01, "{}", seen);
+    }
+
+    #[test]
+    fn russian_roulette_is_unbiased() {
+        // Inside a glowing ellipsoid (not sampled as an emitter, so only
+        // found by bouncing) every bounce adds the emission, scaled by
+        // another `albedo`. Paths get ended at random but the mean is still
+        // the geometric series.
+        let albedo = 0.6;
+        let emission = 0.1;
+        let glowing = Material {
+            diffuse: Float3::new(albedo, albedo, albedo),
+            emission: Float3::new(emission, emission, emission),
+            ..material(0.0, 1.0)
+        };
+        let semiaxes = [Float3::new(2.0, 0.0, 0.0), Float3::new(0.0, 1.0, 0.0), Float3::new(0.0, 0.0, 1.5)];
+
+        let ellipsoid = Ellipsoid::new(Float3::new(0.0, 0.0, 0.0), semiaxes, glowing);
+
+        let mut scene = scene(vec![Primitive::Ellipsoid(ellipsoid)]);
+        scene.integrator = Integrator::PathTracer;
+
+        let seen = mean_radiance(&scene, Float3::new(0.1, 0.2, 0.0), Float3::new(0.3, -0.2, 1.0), 40000);
+        let expected = emission / (1.0 - albedo);
+        assert!((seen.x - expected).abs() < 0.01 * expected, "{} instead of {}", seen.x, expected);
+    }
+
+    // How many samples the adaptive anti-aliasing takes in each pixel of an
+    // 8x8 image of a sphere filling the middle, lit from behind the camera.
+    fn adaptive_sample_counts(aa_threshold: f64) -> Vec<Vec<u32>> {
+        let mut file: SceneFile = serde_json::from_str(
+            r#"{
+                "spheres": [ { "center": [0, 0, 0], "radius": 1, "material": {
+                    "diffuse": [0.8, 0.8, 0.8], "specular_coefficient": 0, "specular_power": 1,
+                    "attenuation": [1, 1, 1], "electric_permittivity": 1,
+                    "magnetic_permeability": 1, "index_of_refraction": 1
+                } } ],
+                "lights": [ { "center": [0, 0, 8], "radius": 0, "color": [1, 1, 1] } ],
+                "camera": { "eye": [0, 0, 4], "target": [0, 0, 0], "fov": 40 },
+                "ambient": [0.1, 0.1, 0.1], "air_attenuation": [1, 1, 1],
+                "aa_type": "Adaptive", "aa_rate": 4, "width": 8, "height": 8
+            }"#,
+        )
+        .unwrap();
+        file.aa_threshold = aa_threshold;
+
+        let scene = Scene::from(file);
+        let mut sampler = create_sampler(SamplerType::Random, samples_per_pixel(&scene), 0);
+
+        (0..8)
+            .map(|y| {
+                (0..8)
+                    .map(|x| {
+                        sampler.start_pixel(x, y);
+
+                        let mut pixel = adaptive_pixel(&scene, x, y, 4, sampler.as_mut());
+                        pixel.region(0, 0, pixel.side - 1);
+
+                        pixel.count
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn adaptive_refines_only_where_samples_differ() {
+        let counts = adaptive_sample_counts(0.05);
+
+        // A 5x5 grid for an `aa_rate` of 4.
+        assert!(counts.iter().flatten().all(|count| (4..=25).contains(count)), "{:?}", counts);
+
+        // Flat colors, the background in the corner and the sphere's
+        // gently shaded middle, only need the pixel's corners.
+        assert_eq!(counts[0][0], 4);
+        assert_eq!(counts[4][4], 4);
+
+        // The sphere's edge crosses pixels along the middle row.
+        assert!(counts[4].iter().any(|count| *count > 4), "{:?}", counts[4]);
+    }
+
+    #[test]
+    fn adaptive_threshold_decides_on_color() {
+        // Off center the shading changes across the pixel, by more than the
+        // small threshold and less than the large one.
+        assert_eq!(adaptive_sample_counts(1e-6)[4][5], 25);
+        assert_eq!(adaptive_sample_counts(1.0)[4][5], 4);
+    }
+
+    #[test]
+    fn filtered_tiles_keep_a_constant_image_constant() {
+        // Scaling by powers of two is exact, so the weights' sums cancel out
+        // exactly.
+        let sky = Float3::new(1.0, 0.5, 0.25);
+
+        for filter in [FilterType::Box, FilterType::Gaussian, FilterType::Mitchell, FilterType::Lanczos].iter() {
+            let mut file: SceneFile = serde_json::from_str(
+                r#"{
+                    "camera": { "eye": [0, 0, 0], "target": [0, 0, -1], "fov": 60 },
+                    "ambient": [0, 0, 0], "air_attenuation": [1, 1, 1],
+                    "aa_type": "MonteCarlo", "aa_rate": 2, "width": 12, "height": 10
+                }"#,
+            )
+            .unwrap();
+            file.filter = *filter;
+
+            let mut scene = Scene::from(file);
+            let environment = Environment::Constant(sky);
+            scene.environment = Some(Arc::new(Background::new(&environment, Path::new("")).unwrap()));
+
+            // Tiles in the middle and against the edges of the image, where
+            // there are fewer samples to go round.
+            for mut tile in create_tiles(12, 10, 4, TileOrder::Scanline) {
+                assert!(render_tile(&scene, &mut tile, 4, &AtomicBool::new(false)));
+
+                for pixel in tile.pixels.iter() {
+                    assert_eq!(*pixel, sky, "{:?} tile at {} {}", filter, tile.x, tile.y);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,755 @@
+use serde::{Serialize, Deserialize};
+
+use std::f64::consts::PI;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::Float3;
+
+/// Where the sample points used for pixels, the lens and lights come from.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum SamplerType {
+    /// Independent uniform random points.
+    #[default]
+    Random,
+    /// One random point in each cell of a grid, with the cells shuffled
+    /// differently for every dimension.
+    Stratified,
+    /// The Halton sequence, randomly shifted in each pixel.
+    Halton,
+    /// The Sobol sequence with hash based Owen scrambling.
+    Sobol,
+    /// The Sobol sequence shifted in each pixel by a blue noise mask, so the
+    /// noise that's left is spread out evenly over the image.
+    BlueNoise,
+}
+
+impl FromStr for SamplerType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = crate::normalize_name(s);
+
+        match name.as_str() {
+            "random" => Ok(SamplerType::Random),
+            "stratified" => Ok(SamplerType::Stratified),
+            "halton" => Ok(SamplerType::Halton),
+            "sobol" => Ok(SamplerType::Sobol),
+            "bluenoise" => Ok(SamplerType::BlueNoise),
+            _ => Err(format!("Unknown sampler '{}'", s)),
+        }
+    }
+}
+
+/// Hands out points in [0, 1)² for each sample of a pixel.
+///
+/// Every call to `next_2d` within a sample moves on to the next dimension, so
+/// the pixel position, lens position and each light sample all get their own
+/// well distributed set of points across the pixel's samples.
+pub trait Sampler {
+    fn start_pixel(&mut self, x: u32, y: u32);
+    /// `index` counts up from 0 for each sample taken in the pixel.
+    fn start_sample(&mut self, index: u32);
+    fn next_2d(&mut self) -> (f64, f64);
+}
+
+/// `samples_per_pixel` is how many samples each pixel is expected to take,
+/// only the stratified sampler needs it to be exact. The points only depend
+/// on `seed` and the pixel, not on the order pixels are sampled in.
+pub fn create_sampler(kind: SamplerType, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+    let state = SamplerState::new(seed);
+
+    match kind {
+        SamplerType::Random => Box::new(RandomSampler { state }),
+        SamplerType::Stratified => {
+            let side = (samples_per_pixel.max(1) as f64).sqrt().ceil() as u32;
+
+            Box::new(StratifiedSampler { state, side })
+        }
+        SamplerType::Halton => Box::new(HaltonSampler { state }),
+        SamplerType::Sobol => Box::new(SobolSampler { state }),
+        SamplerType::BlueNoise => Box::new(BlueNoiseSampler { state }),
+    }
+}
+
+/// Maps a point in [0, 1)² to the unit disk, evenly spread points stay evenly
+/// spread.
+/// https://doi.org/10.1080/10867651.1997.10487479
+pub fn concentric_disk(u: f64, v: f64) -> (f64, f64) {
+    let a = (2.0 * u) - 1.0;
+    let b = (2.0 * v) - 1.0;
+
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, (PI / 4.0) * (b / a))
+    } else {
+        (b, (PI / 2.0) - ((PI / 4.0) * (a / b)))
+    };
+
+    (r * theta.cos(), r * theta.sin())
+}
+
+/// Two unit vectors perpendicular to `w` and each other.
+pub fn tangent_frame(w: &Float3) -> (Float3, Float3) {
+    let helper = if w.x.abs() > 0.9 {
+        Float3::new(0.0, 1.0, 0.0)
+    } else {
+        Float3::new(1.0, 0.0, 0.0)
+    };
+
+    let u = w.cross(&helper).normalize();
+    let v = w.cross(&u);
+
+    (u, v)
+}
+
+/// Maps a point in [0, 1)² to a direction around `normal` (a unit vector),
+/// with more of them closer to it in proportion to the cosine of the angle.
+pub fn cosine_hemisphere(normal: &Float3, u: f64, v: f64) -> Float3 {
+    let (x, y) = concentric_disk(u, v);
+    let z = f64::max(0.0, 1.0 - (x * x) - (y * y)).sqrt();
+
+    let (tangent, bitangent) = tangent_frame(normal);
+
+    (tangent * x) + (bitangent * y) + (normal * z)
+}
+
+/// Maps a point in [0, 1)² to a direction in the cone around `w` (a unit
+/// vector) out to the angle whose cosine is `cos_theta_max`. Directions are
+/// spread evenly over the cone's solid angle, 2π(1 - `cos_theta_max`).
+pub fn uniform_cone(w: &Float3, cos_theta_max: f64, u: f64, v: f64) -> Float3 {
+    let (tangent, bitangent) = tangent_frame(w);
+
+    let cos_theta = 1.0 - (u * (1.0 - cos_theta_max));
+    let sin_theta = f64::max(0.0, 1.0 - (cos_theta * cos_theta)).sqrt();
+    let phi = 2.0 * PI * v;
+
+    (w * cos_theta) + (tangent * (sin_theta * phi.cos())) + (bitangent * (sin_theta * phi.sin()))
+}
+
+/// Maps a point in [0, 1)² to a direction around `w` (a unit vector) in
+/// proportion to cos^`exponent` of the angle to it, a Phong highlight's lobe.
+/// Larger exponents keep them closer to `w`.
+pub fn phong_lobe(w: &Float3, exponent: f64, u: f64, v: f64) -> Float3 {
+    let (tangent, bitangent) = tangent_frame(w);
+
+    let cos_theta = (1.0 - u).powf(1.0 / (exponent + 1.0));
+    let sin_theta = f64::max(0.0, 1.0 - (cos_theta * cos_theta)).sqrt();
+    let phi = 2.0 * PI * v;
+
+    (w * cos_theta) + (tangent * (sin_theta * phi.cos())) + (bitangent * (sin_theta * phi.sin()))
+}
+
+#[derive(Debug, Clone)]
+struct SamplerState {
+    x: u32,
+    y: u32,
+    index: u32,
+    dimension: u32,
+
+    // Scene seed folded down to 32 bits for hashing.
+    seed: u32,
+    // Hash of the scene seed and the pixel.
+    pixel: u32,
+    // Reseeded for every sample.
+    rng: StdRng,
+}
+
+impl SamplerState {
+    fn new(seed: u64) -> Self {
+        SamplerState {
+            x: 0,
+            y: 0,
+            index: 0,
+            dimension: 0,
+            seed: hash((seed as u32) ^ hash((seed >> 32) as u32)),
+            pixel: 0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn start_pixel(&mut self, x: u32, y: u32) {
+        self.x = x;
+        self.y = y;
+        self.index = 0;
+        self.dimension = 0;
+
+        self.pixel = hash(x ^ hash(y ^ self.seed));
+        self.start_sample(0);
+    }
+
+    // The random numbers only depend on the pixel and `index`, so a sample
+    // comes out the same whatever was sampled before it.
+    fn start_sample(&mut self, index: u32) {
+        self.index = index;
+        self.dimension = 0;
+
+        let sample = hash(self.pixel ^ hash(index));
+        self.rng = StdRng::seed_from_u64(((self.seed as u64) << 32) | (sample as u64));
+    }
+
+    // Returns the dimension to use for this draw.
+    fn next_dimension(&mut self) -> u32 {
+        self.dimension += 1;
+        self.dimension - 1
+    }
+
+    // Differs for every pixel & dimension but not between samples.
+    fn seed(&self, dimension: u32) -> u32 {
+        hash(self.x ^ hash(self.y ^ hash(dimension ^ self.seed)))
+    }
+
+    fn random_2d(&mut self) -> (f64, f64) {
+        (self.rng.gen(), self.rng.gen())
+    }
+}
+
+/// https://nullprogram.com/blog/2018/07/31/
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
+    x
+}
+
+fn to_unit(x: u32) -> f64 {
+    (x as f64) / 4294967296.0
+}
+
+struct RandomSampler {
+    state: SamplerState,
+}
+
+impl Sampler for RandomSampler {
+    fn start_pixel(&mut self, x: u32, y: u32) {
+        self.state.start_pixel(x, y);
+    }
+
+    fn start_sample(&mut self, index: u32) {
+        self.state.start_sample(index);
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        self.state.random_2d()
+    }
+}
+
+struct StratifiedSampler {
+    state: SamplerState,
+    // The grid is `side` x `side` cells.
+    side: u32,
+}
+
+/// Random permutation of `0..length` picked by `seed`.
+/// https://graphics.pixar.com/library/MultiJitteredSampling/paper.pdf
+fn permute(mut i: u32, length: u32, seed: u32) -> u32 {
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | (seed >> 27));
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+
+        if i < length {
+            break;
+        }
+    }
+
+    (i.wrapping_add(seed)) % length
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel(&mut self, x: u32, y: u32) {
+        self.state.start_pixel(x, y);
+    }
+
+    fn start_sample(&mut self, index: u32) {
+        self.state.start_sample(index);
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.next_dimension();
+        let cells = self.side * self.side;
+        let jitter = self.state.random_2d();
+
+        // Past the expected number of samples there's no cell left to use.
+        if self.state.index >= cells {
+            return jitter;
+        }
+
+        let cell = permute(self.state.index, cells, self.state.seed(dimension));
+        let side = self.side as f64;
+
+        (
+            ((cell % self.side) as f64 + jitter.0) / side,
+            ((cell / self.side) as f64 + jitter.1) / side,
+        )
+    }
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107,
+    109, 113, 127, 131,
+];
+
+fn radical_inverse(base: u32, mut index: u32) -> f64 {
+    let inverse_base = 1.0 / (base as f64);
+    let mut scale = inverse_base;
+    let mut out = 0.0;
+
+    while index > 0 {
+        out += ((index % base) as f64) * scale;
+        index /= base;
+        scale *= inverse_base;
+    }
+
+    out
+}
+
+struct HaltonSampler {
+    state: SamplerState,
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel(&mut self, x: u32, y: u32) {
+        self.state.start_pixel(x, y);
+    }
+
+    fn start_sample(&mut self, index: u32) {
+        self.state.start_sample(index);
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.next_dimension();
+        let base = (dimension * 2) as usize;
+
+        // Higher dimensions of Halton are badly correlated, past the primes
+        // here it's no better than random.
+        if base + 1 >= PRIMES.len() {
+            return self.state.random_2d();
+        }
+
+        // Shift the sequence differently in each pixel so neighbouring pixels
+        // don't use the same points.
+        let seed = self.state.seed(dimension);
+        let offset = (to_unit(hash(seed)), to_unit(hash(seed ^ 0x5bd1e995)));
+
+        let index = self.state.index + 1;
+        let u = radical_inverse(PRIMES[base], index) + offset.0;
+        let v = radical_inverse(PRIMES[base + 1], index) + offset.1;
+
+        (u.fract(), v.fract())
+    }
+}
+
+// First two dimensions of the Sobol sequence, as 0.32 fixed point.
+fn sobol_2d(index: u32) -> (u32, u32) {
+    let x = index.reverse_bits();
+
+    let mut y = 0;
+    let mut direction = 1 << 31;
+    let mut i = index;
+
+    while i != 0 {
+        if i & 1 != 0 {
+            y ^= direction;
+        }
+
+        i >>= 1;
+        direction ^= direction >> 1;
+    }
+
+    (x, y)
+}
+
+/// Owen scrambling by hashing.
+/// https://psychopath.io/post/2021_01_30_building_a_better_lk_hash
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+
+    x ^= x.wrapping_mul(0x3d20adea);
+    x = x.wrapping_add(seed);
+    x = x.wrapping_mul((seed >> 16) | 1);
+    x ^= x.wrapping_mul(0x05526c56);
+    x ^= x.wrapping_mul(0x53a22864);
+
+    x.reverse_bits()
+}
+
+struct SobolSampler {
+    state: SamplerState,
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel(&mut self, x: u32, y: u32) {
+        self.state.start_pixel(x, y);
+    }
+
+    fn start_sample(&mut self, index: u32) {
+        self.state.start_sample(index);
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.next_dimension();
+        let seed = self.state.seed(dimension);
+
+        // Every dimension reuses the first two of Sobol, shuffling the order
+        // of the points and scrambling them separately keeps the dimensions
+        // from being correlated.
+        let index = owen_scramble(self.state.index, seed);
+        let (x, y) = sobol_2d(index);
+
+        (
+            to_unit(owen_scramble(x, hash(seed ^ 1))),
+            to_unit(owen_scramble(y, hash(seed ^ 2))),
+        )
+    }
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// A 64x64 tiling blue noise mask, each value in [0, 1) used once.
+/// Made with the void and cluster method the first time it's needed.
+/// https://doi.org/10.1117/12.152707
+fn blue_noise_mask() -> &'static [f64] {
+    static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+
+    MASK.get_or_init(|| {
+        let size = BLUE_NOISE_SIZE;
+        let count = size * size;
+
+        // Gaussian energy falloff with wrap around.
+        let sigma = 1.5;
+        let mut kernel = vec![0.0; count];
+        for dy in 0..size {
+            for dx in 0..size {
+                let x = dx.min(size - dx) as f64;
+                let y = dy.min(size - dy) as f64;
+                kernel[(dy * size) + dx] = (-((x * x) + (y * y)) / (2.0 * sigma * sigma)).exp();
+            }
+        }
+
+        let splat = |energy: &mut [f64], at: usize, sign: f64| {
+            let (ax, ay) = (at % size, at / size);
+
+            for y in 0..size {
+                for x in 0..size {
+                    let dx = (x + size - ax) % size;
+                    let dy = (y + size - ay) % size;
+                    energy[(y * size) + x] += sign * kernel[(dy * size) + dx];
+                }
+            }
+        };
+
+        // Largest energy among pixels that are `value`, or smallest.
+        let extreme = |energy: &[f64], pattern: &[bool], value: bool, largest: bool| {
+            let candidates = (0..count).filter(|&i| pattern[i] == value);
+
+            if largest {
+                candidates.max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            } else {
+                candidates.min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            }
+            .unwrap()
+        };
+
+        // Start with a tenth of the pixels set, spread out by moving the
+        // most crowded one to the emptiest spot until that doesn't change.
+        let mut pattern = vec![false; count];
+        let mut energy = vec![0.0; count];
+        let initial = count / 10;
+        let mut placed = 0;
+        let mut i = 0;
+
+        while placed < initial {
+            let at = (hash(i) as usize) % count;
+            i += 1;
+
+            if !pattern[at] {
+                pattern[at] = true;
+                splat(&mut energy, at, 1.0);
+                placed += 1;
+            }
+        }
+
+        for _ in 0..count {
+            let cluster = extreme(&energy, &pattern, true, true);
+            pattern[cluster] = false;
+            splat(&mut energy, cluster, -1.0);
+
+            let void = extreme(&energy, &pattern, false, false);
+            pattern[void] = true;
+            splat(&mut energy, void, 1.0);
+
+            if void == cluster {
+                break;
+            }
+        }
+
+        let mut rank = vec![0; count];
+
+        // Rank the initial pixels by removing the most crowded first.
+        {
+            let mut pattern = pattern.clone();
+            let mut energy = energy.clone();
+
+            for r in (0..initial).rev() {
+                let cluster = extreme(&energy, &pattern, true, true);
+                pattern[cluster] = false;
+                splat(&mut energy, cluster, -1.0);
+                rank[cluster] = r;
+            }
+        }
+
+        // Fill the emptiest spots up to half.
+        let mut r = initial;
+        while r < count / 2 {
+            let void = extreme(&energy, &pattern, false, false);
+            pattern[void] = true;
+            splat(&mut energy, void, 1.0);
+            rank[void] = r;
+            r += 1;
+        }
+
+        // Past half the unset pixels are the minority, so fill the most
+        // crowded of those instead.
+        let mut energy = vec![0.0; count];
+        for i in (0..count).filter(|&i| !pattern[i]) {
+            splat(&mut energy, i, 1.0);
+        }
+
+        while r < count {
+            let cluster = extreme(&energy, &pattern, false, true);
+            pattern[cluster] = true;
+            splat(&mut energy, cluster, -1.0);
+            rank[cluster] = r;
+            r += 1;
+        }
+
+        rank.iter().map(|&r| ((r as f64) + 0.5) / (count as f64)).collect()
+    })
+}
+
+struct BlueNoiseSampler {
+    state: SamplerState,
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel(&mut self, x: u32, y: u32) {
+        self.state.start_pixel(x, y);
+    }
+
+    fn start_sample(&mut self, index: u32) {
+        self.state.start_sample(index);
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.next_dimension();
+
+        // The same points in every pixel, scrambled per dimension only.
+        let seed = hash(dimension ^ self.state.seed);
+        let index = owen_scramble(self.state.index, seed);
+        let (x, y) = sobol_2d(index);
+        let (x, y) = (
+            to_unit(owen_scramble(x, hash(seed ^ 1))),
+            to_unit(owen_scramble(y, hash(seed ^ 2))),
+        );
+
+        // Then shifted by the mask, offset by a different amount for each
+        // dimension and axis so they aren't correlated.
+        let mask = blue_noise_mask();
+        let lookup = |salt: u32| {
+            let offset = hash(seed ^ salt) as usize;
+            let mx = (self.state.x as usize + offset) % BLUE_NOISE_SIZE;
+            let my = (self.state.y as usize + (offset >> 8)) % BLUE_NOISE_SIZE;
+
+            mask[(my * BLUE_NOISE_SIZE) + mx]
+        };
+
+        ((x + lookup(3)).fract(), (y + lookup(4)).fract())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [SamplerType; 5] = [
+        SamplerType::Random,
+        SamplerType::Stratified,
+        SamplerType::Halton,
+        SamplerType::Sobol,
+        SamplerType::BlueNoise,
+    ];
+
+    // The first `dimensions` points of each of `count` samples of pixel (x, y).
+    fn points(sampler: &mut dyn Sampler, x: u32, y: u32, count: u32, dimensions: usize) -> Vec<Vec<(f64, f64)>> {
+        sampler.start_pixel(x, y);
+
+        (0..count)
+            .map(|index| {
+                sampler.start_sample(index);
+                (0..dimensions).map(|_| sampler.next_2d()).collect()
+            })
+            .collect()
+    }
+
+    // Whether each of the `columns` x `rows` cells of [0, 1)² has exactly one
+    // of `points` in it.
+    fn one_per_cell(points: &[(f64, f64)], columns: usize, rows: usize) -> bool {
+        let mut counts = vec![0; columns * rows];
+
+        for (u, v) in points.iter() {
+            let column = (u * columns as f64) as usize;
+            let row = (v * rows as f64) as usize;
+            counts[(row * columns) + column] += 1;
+        }
+
+        counts.iter().all(|count| *count == 1)
+    }
+
+    #[test]
+    fn permute_is_a_bijection() {
+        for &length in [1, 2, 3, 7, 16, 100, 1000].iter() {
+            for seed in 0..20 {
+                let mut seen = vec![false; length as usize];
+
+                for i in 0..length {
+                    let permuted = permute(i, length, hash(seed)) as usize;
+                    assert!(!seen[permuted], "{} is used twice for length {}", permuted, length);
+                    seen[permuted] = true;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn points_are_in_the_unit_square() {
+        for kind in ALL.iter() {
+            let mut sampler = create_sampler(*kind, 64, 7);
+
+            for (x, y) in [(0, 0), (13, 5), (200, 91)].iter() {
+                for sample in points(sampler.as_mut(), *x, *y, 64, 20) {
+                    for (u, v) in sample {
+                        assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v), "{:?} gave {} {}", kind, u, v);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_is_stratified() {
+        for kind in [SamplerType::Sobol, SamplerType::Stratified].iter() {
+            let mut sampler = create_sampler(*kind, 16, 3);
+            let samples = points(sampler.as_mut(), 4, 9, 16, 6);
+
+            for dimension in 0..6 {
+                let points: Vec<(f64, f64)> = samples.iter().map(|sample| sample[dimension]).collect();
+
+                assert!(one_per_cell(&points, 4, 4), "{:?} dimension {}", kind, dimension);
+
+                if *kind == SamplerType::Sobol {
+                    for &(columns, rows) in [(16, 1), (8, 2), (2, 8), (1, 16)].iter() {
+                        assert!(one_per_cell(&points, columns, rows), "dimension {}", dimension);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn halton_is_evenly_spread() {
+        // Shifted in each pixel so the points don't line up with the cells,
+        // but they stay evenly spaced around [0, 1) for a power of the base.
+        let spacing = |values: &mut Vec<f64>| {
+            values.sort_by(|a, b| a.total_cmp(b));
+
+            let mut gaps: Vec<f64> = values.windows(2).map(|pair| pair[1] - pair[0]).collect();
+            gaps.push(values[0] + 1.0 - values[values.len() - 1]);
+
+            let smallest = gaps.iter().cloned().fold(f64::INFINITY, f64::min);
+            let largest = gaps.iter().cloned().fold(0.0, f64::max);
+
+            (smallest * values.len() as f64, largest * values.len() as f64)
+        };
+
+        let mut sampler = create_sampler(SamplerType::Halton, 16, 11);
+
+        for (axis, count) in [(0, 16), (1, 27)].iter() {
+            let samples = points(sampler.as_mut(), 21, 2, *count, 1);
+            let mut values: Vec<f64> = samples
+                .iter()
+                .map(|sample| if *axis == 0 { sample[0].0 } else { sample[0].1 })
+                .collect();
+
+            let (smallest, largest) = spacing(&mut values);
+            assert!(smallest > 0.49 && largest < 2.01, "axis {} gaps {} to {}", axis, smallest, largest);
+        }
+    }
+
+    #[test]
+    fn same_seed_pixel_and_sample_give_the_same_points() {
+        for kind in ALL.iter() {
+            let mut sampler = create_sampler(*kind, 16, 42);
+            let first = points(sampler.as_mut(), 10, 20, 16, 8);
+
+            // Other pixels in between don't change anything.
+            points(sampler.as_mut(), 11, 20, 16, 8);
+            assert_eq!(points(sampler.as_mut(), 10, 20, 16, 8), first, "{:?}", kind);
+
+            // Neither does a new sampler or starting a sample over.
+            let mut other = create_sampler(*kind, 16, 42);
+            assert_eq!(points(other.as_mut(), 10, 20, 16, 8), first, "{:?}", kind);
+
+            other.start_sample(5);
+            other.next_2d();
+            other.start_sample(5);
+            assert_eq!(other.next_2d(), first[5][0], "{:?}", kind);
+
+            // Another seed does.
+            let mut reseeded = create_sampler(*kind, 16, 43);
+            assert_ne!(points(reseeded.as_mut(), 10, 20, 16, 8), first, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn blue_noise_mask_is_built_once_and_uses_every_value() {
+        let mask = blue_noise_mask();
+        assert!(std::ptr::eq(mask, blue_noise_mask()));
+
+        let count = BLUE_NOISE_SIZE * BLUE_NOISE_SIZE;
+        let mut ranks: Vec<usize> = mask.iter().map(|value| (value * count as f64) as usize).collect();
+        ranks.sort_unstable();
+
+        assert_eq!(ranks, (0..count).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,623 @@
+use serde::{Serialize, Deserialize};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use super::bvh::Bvh;
+use super::camera::{Camera, Lens, LookAt, Projection};
+use super::emitter::Emitter;
+use super::environment::{Background, Environment};
+use super::shapes::*;
+use super::filter::FilterType;
+use super::render::Integrator;
+use super::sampler::SamplerType;
+use super::stereo::Stereo;
+
+use super::Float3;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AntiAliasType {
+    None,
+    SuperSample,
+    MonteCarlo,
+    /// Samples the corners of each pixel and keeps splitting it where they
+    /// differ by more than `aa_threshold`, down to `1 / aa_rate` of a pixel.
+    Adaptive,
+}
+
+impl FromStr for AntiAliasType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = crate::normalize_name(s);
+
+        match name.as_str() {
+            "none" => Ok(AntiAliasType::None),
+            "supersample" => Ok(AntiAliasType::SuperSample),
+            "montecarlo" => Ok(AntiAliasType::MonteCarlo),
+            "adaptive" => Ok(AntiAliasType::Adaptive),
+            _ => Err(format!("Unknown anti-alias type '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+
+    /// The file isn't valid json or doesn't match the scene layout.
+    /// `path` is where in the document the problem is, e.g. `spheres[0].radius`.
+    Parse {
+        line: usize,
+        column: usize,
+        path: String,
+        message: String,
+    },
+
+    /// The file parsed but describes a scene that can't be rendered.
+    Invalid { path: String, message: String },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "{}", err),
+            SceneError::Parse { line, column, path, message } => {
+                if path.is_empty() || path == "." || path == "?" {
+                    write!(f, "{}:{}: {}", line, column, message)
+                } else {
+                    write!(f, "{}:{}: {} (at `{}`)", line, column, message, path)
+                }
+            }
+            SceneError::Invalid { path, message } => write!(f, "`{}`: {}", path, message),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SceneError {
+    fn from(err: io::Error) -> Self {
+        SceneError::Io(err)
+    }
+}
+
+impl SceneError {
+    fn from_json(path: String, err: serde_json::Error) -> Self {
+        if err.is_io() {
+            return SceneError::Io(err.into());
+        }
+
+        // serde_json appends the location to its message, it's reported
+        // separately here.
+        let message = err.to_string();
+        let suffix = format!(" at line {} column {}", err.line(), err.column());
+        let message = message.strip_suffix(&suffix).unwrap_or(&message).to_string();
+
+        SceneError::Parse {
+            line: err.line(),
+            column: err.column(),
+            path,
+            message,
+        }
+    }
+}
+
+impl From<serde_path_to_error::Error<serde_json::Error>> for SceneError {
+    fn from(err: serde_path_to_error::Error<serde_json::Error>) -> Self {
+        let path = err.path().to_string();
+
+        SceneError::from_json(path, err.into_inner())
+    }
+}
+
+fn default_aa_threshold() -> f64 {
+    0.05
+}
+
+fn default_shadow_samples() -> u32 {
+    1
+}
+
+fn is_default_aa_threshold(aa_threshold: &f64) -> bool {
+    *aa_threshold == default_aa_threshold()
+}
+
+fn is_default_shadow_samples(shadow_samples: &u32) -> bool {
+    *shadow_samples == default_shadow_samples()
+}
+
+/// Layout of a scene json file.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SceneFile {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shapes: Vec<Primitive>,
+
+    // Older files list each type of shape separately, these are appended to
+    // `shapes` when the scene is loaded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spheres: Vec<Sphere>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rhombohedrons: Vec<Rhombohedron>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub polygons: Vec<Polygon>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ellipsoids: Vec<Ellipsoid>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lights: Vec<Light>,
+
+    pub ambient: Float3,
+    pub air_attenuation: Float3,
+
+    /// Seen where rays miss everything and lights the scene, without one
+    /// that's black.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<Environment>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera: Option<LookAt>,
+
+    // Older files give the viewport directly instead of a `camera`, these are
+    // ignored if there is one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub viewport_origin: Option<Float3>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub viewport_x_axis: Option<Float3>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub viewport_y_axis: Option<Float3>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eye_position: Option<Float3>,
+
+    /// Adds depth of field, works with either way of giving the camera.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lens: Option<Lens>,
+
+    #[serde(default, skip_serializing_if = "crate::is_default")]
+    pub projection: Projection,
+
+    /// Renders a left & right eye pair instead of a single image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stereo: Option<Stereo>,
+
+    pub aa_type: AntiAliasType,
+    pub aa_rate: u8,
+    #[serde(default = "default_aa_threshold", skip_serializing_if = "is_default_aa_threshold")]
+    pub aa_threshold: f64,
+    #[serde(default, skip_serializing_if = "crate::is_default")]
+    pub sampler: SamplerType,
+    /// Renders with the same seed come out identical.
+    #[serde(default, skip_serializing_if = "crate::is_default")]
+    pub seed: u64,
+
+    #[serde(default, skip_serializing_if = "crate::is_default")]
+    pub filter: FilterType,
+    /// In pixels, defaults to one that suits `filter`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter_radius: Option<f64>,
+
+    /// Shadow rays per light with a radius, more give smoother penumbras.
+    /// Lights can override it.
+    #[serde(default = "default_shadow_samples", skip_serializing_if = "is_default_shadow_samples")]
+    pub shadow_samples: u32,
+
+    /// Whitted style by default, or path tracing for light bouncing between
+    /// surfaces.
+    #[serde(default, skip_serializing_if = "crate::is_default")]
+    pub integrator: Integrator,
+
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone)]
+pub struct Scene {
+    pub shapes: Vec<Primitive>,
+    pub lights: Vec<Light>,
+
+    pub ambient: Float3,
+    pub air_attenuation: Float3,
+
+    /// The scene file's `environment` with its image loaded. Only
+    /// `Scene::from_file` can load it, `Scene::from` leaves it empty.
+    pub environment: Option<Arc<Background>>,
+
+    pub camera: Camera,
+    /// What `camera` was made from, kept so it can be remade when the
+    /// resolution changes. `None` for scenes that give the viewport directly.
+    pub look_at: Option<LookAt>,
+    pub stereo: Option<Stereo>,
+
+    pub aa_type: AntiAliasType,
+    pub aa_rate: u8,
+    pub aa_threshold: f64,
+    pub sampler: SamplerType,
+    pub seed: u64,
+    pub filter: FilterType,
+    pub filter_radius: Option<f64>,
+    pub shadow_samples: u32,
+    pub integrator: Integrator,
+
+    pub width: u32,
+    pub height: u32,
+
+    /// Used to find which shapes a ray might hit.
+    pub bvh: Bvh,
+    /// The shapes that give off light.
+    pub emitters: Vec<Emitter>,
+}
+
+impl SceneFile {
+    /// Catches values that parse fine but can't be rendered.
+    pub fn validate(&self) -> Result<(), SceneError> {
+        let invalid = |path: &str, message: &str| {
+            Err(SceneError::Invalid {
+                path: path.to_string(),
+                message: message.to_string(),
+            })
+        };
+
+        if self.width == 0 {
+            return invalid("width", "must be at least 1");
+        }
+
+        if self.height == 0 {
+            return invalid("height", "must be at least 1");
+        }
+
+        if self.aa_rate == 0 {
+            return invalid("aa_rate", "must be at least 1");
+        }
+
+        if self.aa_threshold < 0.0 || !self.aa_threshold.is_finite() {
+            return invalid("aa_threshold", "can't be negative");
+        }
+
+        if let Some(radius) = self.filter_radius {
+            if radius <= 0.0 || !radius.is_finite() {
+                return invalid("filter_radius", "must be greater than zero");
+            }
+        }
+
+        if self.shadow_samples == 0 {
+            return invalid("shadow_samples", "must be at least 1");
+        }
+
+        // Without a closed volume there's nothing to put bounds around.
+        let rhombohedrons = self
+            .shapes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, shape)| match shape {
+                Primitive::Rhombohedron(rhombohedron) => Some((format!("shapes[{}]", i), rhombohedron)),
+                _ => None,
+            })
+            .chain(
+                self.rhombohedrons
+                    .iter()
+                    .enumerate()
+                    .map(|(i, rhombohedron)| (format!("rhombohedrons[{}]", i), rhombohedron)),
+            );
+
+        for (path, rhombohedron) in rhombohedrons {
+            if let Some((field, message)) = rhombohedron.problem() {
+                return invalid(&format!("{}.{}", path, field), &message);
+            }
+        }
+
+        for (i, light) in self.lights.iter().enumerate() {
+            if let Some((field, message)) = light.problem() {
+                return invalid(&format!("lights[{}].{}", i, field), &message);
+            }
+        }
+
+        match self.camera {
+            Some(look_at) => {
+                if let Some((field, message)) = look_at.problem() {
+                    return invalid(&format!("camera.{}", field), &message);
+                }
+            }
+            None if self.legacy_camera().is_none() => {
+                return invalid(
+                    "camera",
+                    "missing, the scene needs a `camera` (or `viewport_origin`, `viewport_x_axis`, `viewport_y_axis` and `eye_position`)",
+                );
+            }
+            None => {}
+        }
+
+        if let Some((field, message)) = self.lens.and_then(|lens| lens.problem()) {
+            return invalid(&format!("lens.{}", field), &message);
+        }
+
+        if let Some((field, message)) = self.projection.problem() {
+            return invalid(&format!("projection.{}", field), &message);
+        }
+
+        if let Some((field, message)) = self.stereo.and_then(|stereo| stereo.problem()) {
+            return invalid(&format!("stereo.{}", field), &message);
+        }
+
+        if let Some((field, message)) = self.environment.as_ref().and_then(|environment| environment.problem()) {
+            return invalid(&format!("environment.{}", field), &message);
+        }
+
+        Ok(())
+    }
+
+    /// The camera given by the older viewport fields, if they're all there.
+    pub fn legacy_camera(&self) -> Option<Camera> {
+        Some(Camera {
+            eye: self.eye_position?,
+            viewport_origin: self.viewport_origin?,
+            viewport_x_axis: self.viewport_x_axis?,
+            viewport_y_axis: self.viewport_y_axis?,
+            lens: self.lens,
+            projection: self.projection,
+        })
+    }
+}
+
+impl From<SceneFile> for Scene {
+    fn from(file: SceneFile) -> Self {
+        let zero = Float3::new(0.0, 0.0, 0.0);
+        let camera = match file.camera {
+            Some(look_at) => Camera {
+                lens: file.lens,
+                projection: file.projection,
+                ..Camera::from_look_at(&look_at, file.width as f64 / file.height as f64)
+            },
+            None => file.legacy_camera().unwrap_or(Camera {
+                eye: zero,
+                viewport_origin: zero,
+                viewport_x_axis: zero,
+                viewport_y_axis: zero,
+                lens: None,
+                projection: Projection::Perspective,
+            }),
+        };
+
+        let mut shapes = file.shapes;
+        shapes.extend(file.spheres.into_iter().map(Primitive::Sphere));
+        shapes.extend(file.rhombohedrons.into_iter().map(Primitive::Rhombohedron));
+        shapes.extend(file.polygons.into_iter().map(Primitive::Polygon));
+        shapes.extend(file.ellipsoids.into_iter().map(Primitive::Ellipsoid));
+
+        let bvh = Bvh::new(&shapes);
+        let emitters = shapes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, shape)| Emitter::from_shape(index, shape))
+            .collect();
+
+        Scene {
+            shapes,
+            lights: file.lights,
+            ambient: file.ambient,
+            air_attenuation: file.air_attenuation,
+            environment: None,
+            camera,
+            look_at: file.camera,
+            stereo: file.stereo,
+            aa_type: file.aa_type,
+            aa_rate: file.aa_rate,
+            aa_threshold: file.aa_threshold,
+            sampler: file.sampler,
+            seed: file.seed,
+            filter: file.filter,
+            filter_radius: file.filter_radius,
+            shadow_samples: file.shadow_samples,
+            integrator: file.integrator,
+            width: file.width,
+            height: file.height,
+            bvh,
+            emitters,
+        }
+    }
+}
+
+impl From<&Scene> for SceneFile {
+    fn from(scene: &Scene) -> Self {
+        let legacy = match scene.look_at {
+            Some(_) => None,
+            None => Some(scene.camera),
+        };
+
+        SceneFile {
+            shapes: scene.shapes.clone(),
+            spheres: Vec::new(),
+            rhombohedrons: Vec::new(),
+            polygons: Vec::new(),
+            ellipsoids: Vec::new(),
+            lights: scene.lights.clone(),
+            ambient: scene.ambient,
+            air_attenuation: scene.air_attenuation,
+            environment: scene.environment.as_ref().map(|background| background.environment.clone()),
+            camera: scene.look_at,
+            viewport_origin: legacy.map(|camera| camera.viewport_origin),
+            viewport_x_axis: legacy.map(|camera| camera.viewport_x_axis),
+            viewport_y_axis: legacy.map(|camera| camera.viewport_y_axis),
+            eye_position: legacy.map(|camera| camera.eye),
+            lens: scene.camera.lens,
+            projection: scene.camera.projection,
+            stereo: scene.stereo,
+            aa_type: scene.aa_type,
+            aa_rate: scene.aa_rate,
+            aa_threshold: scene.aa_threshold,
+            sampler: scene.sampler,
+            seed: scene.seed,
+            filter: scene.filter,
+            filter_radius: scene.filter_radius,
+            shadow_samples: scene.shadow_samples,
+            integrator: scene.integrator,
+            width: scene.width,
+            height: scene.height,
+        }
+    }
+}
+
+impl SceneFile {
+    pub fn from_file(filename: &str) -> Result<SceneFile, SceneError> {
+        let file = File::open(filename)?;
+        let reader = BufReader::new(file);
+
+        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+        let file: SceneFile = serde_path_to_error::deserialize(&mut deserializer)?;
+
+        // Reject trailing garbage after the scene.
+        deserializer
+            .end()
+            .map_err(|err| SceneError::from_json(String::new(), err))?;
+
+        Ok(file)
+    }
+
+    pub fn to_file(&self, filename: &str) -> Result<(), SceneError> {
+        let file = File::create(filename)?;
+        let writer = BufWriter::new(file);
+
+        serde_json::to_writer_pretty(writer, self).map_err(io::Error::from)?;
+
+        Ok(())
+    }
+}
+
+impl Scene {
+    pub fn from_file(filename: &str) -> Result<Scene, SceneError> {
+        let file = SceneFile::from_file(filename)?;
+        file.validate()?;
+
+        // Image paths are relative to the scene file.
+        let directory = Path::new(filename).parent().unwrap_or_else(|| Path::new(""));
+
+        let background = match file.environment {
+            Some(ref environment) => Some(Background::new(environment, directory).map_err(|message| {
+                SceneError::Invalid {
+                    path: "environment.path".to_string(),
+                    message,
+                }
+            })?),
+            None => None,
+        };
+
+        let mut scene = Scene::from(file);
+        scene.environment = background.map(Arc::new);
+
+        Ok(scene)
+    }
+
+    pub fn to_file(&self, filename: &str) -> Result<(), SceneError> {
+        SceneFile::from(self).to_file(filename)
+    }
+
+    /// Changes the image size, a look-at camera without its own aspect ratio
+    /// is remade to match.
+    pub fn set_resolution(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+
+        if let Some(look_at) = self.look_at {
+            self.camera = Camera {
+                lens: self.camera.lens,
+                projection: self.camera.projection,
+                ..Camera::from_look_at(&look_at, width as f64 / height as f64)
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> Rhombohedron {
+        let material = Material {
+            diffuse: Float3::new(1.0, 1.0, 1.0),
+            specular_coefficient: 0.0,
+            specular_power: 1.0,
+            attenuation: Float3::new(1.0, 1.0, 1.0),
+            electric_permittivity: 1.0,
+            magnetic_permeability: 1.0,
+            index_of_refraction: 1.0,
+            emission: Float3::new(0.0, 0.0, 0.0),
+        };
+
+        Rhombohedron::from_corner_and_edges(
+            Float3::new(0.0, 0.0, 0.0),
+            Float3::new(1.0, 0.0, 0.0),
+            Float3::new(0.0, 1.0, 0.0),
+            Float3::new(0.0, 0.0, 1.0),
+            material,
+        )
+    }
+
+    fn invalid_path(file: SceneFile) -> String {
+        match file.validate() {
+            Err(SceneError::Invalid { path, .. }) => path,
+            other => panic!("expected the box to be rejected, got {:?}", other),
+        }
+    }
+
+    fn with_shape(rhombohedron: Rhombohedron) -> SceneFile {
+        let mut file: SceneFile = serde_json::from_str(
+            r#"{
+                "ambient": [0, 0, 0], "air_attenuation": [1, 1, 1],
+                "aa_type": "None", "aa_rate": 1, "width": 4, "height": 4,
+                "camera": { "eye": [0, 0, 5], "target": [0, 0, 0], "fov": 40 }
+            }"#,
+        )
+        .unwrap();
+        file.shapes = vec![Primitive::Rhombohedron(rhombohedron)];
+
+        file
+    }
+
+    #[test]
+    fn closed_boxes_are_valid() {
+        assert!(unit_box().problem().is_none());
+        with_shape(unit_box()).validate().unwrap();
+    }
+
+    #[test]
+    fn open_plane_sets_are_invalid() {
+        // The unit box's planes face -y, +y, -x, +x, -z & +z in that order.
+        let mut flipped = unit_box();
+        flipped.planes[1].normal = -flipped.planes[1].normal;
+        assert_eq!(invalid_path(with_shape(flipped)), "shapes[0].planes[0]");
+
+        // Turning the z pair into the xy diagonal leaves the box open along z.
+        let mut coplanar = unit_box();
+        coplanar.planes[4].normal = Float3::new(-1.0, -1.0, 0.0).normalize();
+        coplanar.planes[5] = Plane {
+            normal: Float3::new(1.0, 1.0, 0.0).normalize(),
+            point: Float3::new(1.0, 1.0, 0.0),
+        };
+        assert_eq!(invalid_path(with_shape(coplanar)), "shapes[0].planes");
+
+        // With the z planes swapped nothing is behind both of them.
+        let mut apart = unit_box();
+        apart.planes[4].point = Float3::new(0.0, 0.0, 1.0);
+        apart.planes[5].point = Float3::new(0.0, 0.0, 0.0);
+        assert_eq!(invalid_path(with_shape(apart)), "shapes[0].planes[5]");
+
+        let mut file = with_shape(unit_box());
+        file.rhombohedrons = vec![flipped];
+        assert_eq!(invalid_path(file), "rhombohedrons[0].planes[0]");
+    }
+}
@@ -0,0 +1,680 @@
+extern crate nalgebra as na;
+
+use serde::{Serialize, Deserialize};
+
+use super::Float3;
+use super::Intersection;
+pub type Float3x3 = na::Matrix3<f64>;
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Material {
+    pub diffuse: Float3,
+    pub specular_coefficient: f64,
+    pub specular_power: f64,
+    pub attenuation: Float3,
+    pub electric_permittivity: f64,
+    pub magnetic_permeability: f64,
+    pub index_of_refraction: f64,
+
+    /// Light given off by the surface (radiance), makes the shape an area
+    /// light.
+    #[serde(default, skip_serializing_if = "is_black")]
+    pub emission: Float3,
+}
+
+fn is_black(color: &Float3) -> bool {
+    color.iter().all(|c| *c == 0.0)
+}
+
+impl Material {
+    pub fn is_emissive(&self) -> bool {
+        self.emission.iter().any(|c| *c > 0.0)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Ray {
+    pub origin: Float3,
+    pub direction: Float3,
+}
+
+/// Anything that can be placed in a scene.
+///
+/// To add a new type of shape implement this for it and add it to `Primitive`.
+pub trait Shape {
+    /// Closest intersection in front of the ray's origin that's no further
+    /// than `max_t` along it.
+    fn intersect(&self, ray: &Ray, max_t: f64) -> Option<Intersection>;
+    fn bounds(&self) -> Aabb;
+    fn material(&self) -> &Material;
+}
+
+/// Axis aligned bounding box
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    pub min: Float3,
+    pub max: Float3,
+}
+
+impl Aabb {
+    /// Contains nothing, growing it by anything gives that thing's bounds.
+    pub fn empty() -> Self {
+        Aabb {
+            min: Float3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Float3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    /// Used for shapes that aren't bounded (or whose bounds can't be found).
+    pub fn infinite() -> Self {
+        Aabb {
+            min: Float3::new(f64::MIN, f64::MIN, f64::MIN),
+            max: Float3::new(f64::MAX, f64::MAX, f64::MAX),
+        }
+    }
+
+    pub fn from_points<'a, I: IntoIterator<Item = &'a Float3>>(points: I) -> Self {
+        let mut out = Aabb::empty();
+
+        for point in points {
+            out.grow_point(point);
+        }
+
+        out
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn grow_point(&mut self, point: &Float3) {
+        self.min = self.min.inf(point);
+        self.max = self.max.sup(point);
+    }
+
+    pub fn grow(&mut self, other: &Aabb) {
+        self.min = self.min.inf(&other.min);
+        self.max = self.max.sup(&other.max);
+    }
+
+    pub fn center(&self) -> Float3 {
+        // Halve before adding so infinite bounds don't overflow.
+        (self.min * 0.5) + (self.max * 0.5)
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let d = self.max - self.min;
+
+        2.0 * ((d.x * d.y) + (d.y * d.z) + (d.z * d.x))
+    }
+
+    /// Slab test, returns the distance along the ray where it enters the box.
+    /// `inverse_direction` is 1/ray.direction per component.
+    pub fn ray_entry(&self, ray: &Ray, inverse_direction: &Float3, max_t: f64) -> Option<f64> {
+        let mut t_min = 0.0;
+        let mut t_max = max_t;
+
+        for axis in 0..3 {
+            let t1 = (self.min[axis] - ray.origin[axis]) * inverse_direction[axis];
+            let t2 = (self.max[axis] - ray.origin[axis]) * inverse_direction[axis];
+
+            t_min = f64::max(t_min, f64::min(t1, t2));
+            t_max = f64::min(t_max, f64::max(t1, t2));
+        }
+
+        if t_min <= t_max {
+            Some(t_min)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Sphere {
+    pub center: Float3,
+    pub radius: f64,
+
+    pub material: Material,
+}
+
+impl Shape for Sphere {
+    fn intersect(&self, ray: &Ray, max_t: f64) -> Option<Intersection> {
+        super::ray_vs_sphere(ray, self, max_t)
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Aabb {
+        let r = Float3::new(self.radius, self.radius, self.radius);
+
+        Aabb {
+            min: self.center - r,
+            max: self.center + r,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Plane {
+    pub normal: Float3,
+    pub point: Float3,
+}
+
+// 'Box'
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Rhombohedron {
+    pub planes: [Plane; 6],
+
+    pub material: Material,
+}
+
+impl Rhombohedron {
+    pub fn from_corner_and_edges(
+        corner: Float3,
+        length: Float3,
+        width: Float3,
+        height: Float3,
+        material: Material,
+    ) -> Rhombohedron {
+        let plane_0 = Plane {
+            point: corner,
+            normal: length.cross(&height).normalize(),
+        };
+        let plane_1 = Plane {
+            point: corner + width,
+            normal: -plane_0.normal,
+        };
+        let plane_2 = Plane {
+            point: corner,
+            normal: height.cross(&width).normalize(),
+        };
+        let plane_3 = Plane {
+            point: corner + length,
+            normal: -plane_2.normal,
+        };
+        let plane_4 = Plane {
+            point: corner,
+            normal: width.cross(&length).normalize(),
+        };
+        let plane_5 = Plane {
+            point: corner + height,
+            normal: -plane_4.normal,
+        };
+
+        Rhombohedron {
+            planes: [plane_0, plane_1, plane_2, plane_3, plane_4, plane_5],
+            material,
+        }
+    }
+
+    /// Why the planes don't bound a closed volume, if they don't, as the path
+    /// of the offending value and what's wrong with it.
+    pub fn problem(&self) -> Option<(String, String)> {
+        const TOLERANCE: f64 = 1e-6;
+
+        let planes = self.planes;
+
+        for (i, plane) in planes.iter().enumerate() {
+            let length = plane.normal.norm();
+
+            if length == 0.0 || !length.is_finite() {
+                return Some((format!("planes[{}].normal", i), "normal has no direction".to_string()));
+            }
+        }
+
+        // The planes have to come in three opposite facing pairs, whose
+        // normals aren't coplanar, for them to bound a closed volume.
+        let mut pairs = Vec::new();
+        let mut paired = [false; 6];
+
+        for i in 0..6 {
+            if paired[i] {
+                continue;
+            }
+
+            let normal = planes[i].normal.normalize();
+            let opposite = ((i + 1)..6)
+                .find(|&j| !paired[j] && normal.dot(&planes[j].normal.normalize()) < TOLERANCE - 1.0);
+
+            match opposite {
+                Some(j) => {
+                    paired[i] = true;
+                    paired[j] = true;
+                    pairs.push((i, j));
+                }
+                None => {
+                    return Some((
+                        format!("planes[{}]", i),
+                        "no other plane faces the opposite way, the planes don't bound a closed volume".to_string(),
+                    ));
+                }
+            }
+        }
+
+        let normals = Float3x3::from_columns(&[
+            planes[pairs[0].0].normal.normalize(),
+            planes[pairs[1].0].normal.normalize(),
+            planes[pairs[2].0].normal.normalize(),
+        ]);
+
+        if normals.determinant().abs() < TOLERANCE {
+            return Some((
+                "planes".to_string(),
+                "the plane normals are coplanar, the planes don't bound a closed volume".to_string(),
+            ));
+        }
+
+        for (i, j) in pairs {
+            // Each plane keeps what's behind it, so the other plane of a pair
+            // has to be behind it too or nothing is inside both.
+            let normal = planes[i].normal.normalize();
+
+            if (planes[i].point - planes[j].point).dot(&normal) <= 0.0 {
+                return Some((
+                    format!("planes[{}]", j),
+                    format!("faces away from `planes[{}]`, there's nothing inside both", i),
+                ));
+            }
+        }
+
+        None
+    }
+
+    /// Finds the corners by intersecting every set of three planes and
+    /// keeping the points that are inside all six half-spaces.
+    pub fn corners(&self) -> Vec<Float3> {
+        let mut corners = Vec::new();
+
+        for i in 0..6 {
+            for j in (i + 1)..6 {
+                for k in (j + 1)..6 {
+                    let planes = [self.planes[i], self.planes[j], self.planes[k]];
+
+                    let m = Float3x3::from_rows(&[
+                        planes[0].normal.transpose(),
+                        planes[1].normal.transpose(),
+                        planes[2].normal.transpose(),
+                    ]);
+
+                    let d = Float3::new(
+                        planes[0].normal.dot(&planes[0].point),
+                        planes[1].normal.dot(&planes[1].point),
+                        planes[2].normal.dot(&planes[2].point),
+                    );
+
+                    if let Some(inverse) = m.try_inverse() {
+                        let point = inverse * d;
+
+                        let inside = self
+                            .planes
+                            .iter()
+                            .all(|plane| (point - plane.point).dot(&plane.normal) < 1e-9);
+
+                        if inside {
+                            corners.push(point);
+                        }
+                    }
+                }
+            }
+        }
+
+        corners
+    }
+}
+
+impl Shape for Rhombohedron {
+    fn intersect(&self, ray: &Ray, max_t: f64) -> Option<Intersection> {
+        super::ray_vs_rhombohedron(ray, self, max_t)
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Aabb {
+        let corners = self.corners();
+
+        if corners.is_empty() {
+            return Aabb::infinite();
+        }
+
+        Aabb::from_points(corners.iter())
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Triangle {
+    pub vertices: [Float3; 3],
+    pub edges: [Float3; 2],
+    pub normal: Float3,
+}
+
+impl Triangle {
+    pub fn from_vertices(vertices: [Float3; 3]) -> Self {
+        let edges: [Float3; 2] = [vertices[1] - vertices[0], vertices[2] - vertices[0]];
+        let normal = edges[0].cross(&edges[1]).normalize();
+
+        Triangle {
+            vertices,
+            edges,
+            normal,
+        }
+    }
+
+    pub fn contains(&self, point: &Float3) -> bool {
+        let aa = self.edges[0].dot(&self.edges[0]);
+        let bb = self.edges[1].dot(&self.edges[1]);
+        let ab = self.edges[0].dot(&self.edges[1]);
+
+        let pc = point - self.vertices[0];
+        let inv_det = 1.0 / ((aa * bb) - (ab * ab));
+
+        let x_ = pc.dot(&self.edges[0]);
+        let y_ = pc.dot(&self.edges[1]);
+
+        let x = inv_det * ((bb * x_) + (-ab * y_));
+        let y = inv_det * ((-ab * x_) + (aa * y_));
+
+        (x > 0.0) && (y > 0.0) && ((x + y) < 1.0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Polygon {
+    pub triangles: Vec<Triangle>,
+    pub plane: Plane,
+
+    pub material: Material,
+}
+
+impl Polygon {
+    pub fn from_vertices(vertices: Vec<Float3>, material: Material) -> Self {
+        let mut triangles = Vec::new();
+
+        for i in 1..(vertices.len() - 1) {
+            triangles.push(Triangle::from_vertices([
+                vertices[0],
+                vertices[i],
+                vertices[i + 1],
+            ]));
+        }
+
+        let plane = Plane {
+            normal: triangles[0].normal,
+            point: vertices[0],
+        };
+
+        Polygon {
+            triangles,
+            plane,
+            material,
+        }
+    }
+}
+
+impl Shape for Polygon {
+    fn intersect(&self, ray: &Ray, max_t: f64) -> Option<Intersection> {
+        super::ray_vs_polygon(ray, self, max_t)
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::from_points(self.triangles.iter().flat_map(|triangle| triangle.vertices.iter()))
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Ellipsoid {
+    pub center: Float3,
+    pub inverse: Float3x3,
+    pub inverse_transpose: Float3x3,
+
+    pub material: Material,
+}
+
+impl Ellipsoid {
+    pub fn new(center: Float3, semiaxes: [Float3; 3], material: Material) -> Self {
+        let m = Float3x3::from_columns(&semiaxes);
+
+        let inverse = m.try_inverse().expect("Ellipsoid transform non-invertable");
+        let inverse_transpose = inverse.transpose();
+
+        Ellipsoid { center, inverse, inverse_transpose, material }
+    }
+}
+
+impl Shape for Ellipsoid {
+    fn intersect(&self, ray: &Ray, max_t: f64) -> Option<Intersection> {
+        super::ray_vs_ellipsoid(ray, self, max_t)
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Aabb {
+        // The ellipsoid is the unit sphere transformed by `m`, on each axis it
+        // extends by the length of that row of `m`.
+        let m = match self.inverse.try_inverse() {
+            Some(m) => m,
+            None => return Aabb::infinite(),
+        };
+
+        let extents = Float3::new(m.row(0).norm(), m.row(1).norm(), m.row(2).norm());
+
+        Aabb {
+            min: self.center - extents,
+            max: self.center + extents,
+        }
+    }
+}
+
+/// Every type of shape a scene can hold. In scene files each is tagged with
+/// its type, e.g. `{ "type": "Sphere", "center": ... }`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Primitive {
+    Sphere(Sphere),
+    Rhombohedron(Rhombohedron),
+    Polygon(Polygon),
+    Ellipsoid(Ellipsoid),
+}
+
+impl Primitive {
+    fn shape(&self) -> &dyn Shape {
+        match self {
+            Primitive::Sphere(shape) => shape,
+            Primitive::Rhombohedron(shape) => shape,
+            Primitive::Polygon(shape) => shape,
+            Primitive::Ellipsoid(shape) => shape,
+        }
+    }
+}
+
+impl Shape for Primitive {
+    fn intersect(&self, ray: &Ray, max_t: f64) -> Option<Intersection> {
+        self.shape().intersect(ray, max_t)
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.shape().bounds()
+    }
+
+    fn material(&self) -> &Material {
+        self.shape().material()
+    }
+}
+
+fn default_spot_exponent() -> f64 {
+    1.0
+}
+
+fn is_default_spot_exponent(exponent: &f64) -> bool {
+    *exponent == default_spot_exponent()
+}
+
+/// What sort of light a `Light` is.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum LightKind {
+    /// Shines in every direction from `center`.
+    #[default]
+    Point,
+    /// Infinitely far away shining along `direction`, like the sun. `angle` is
+    /// how wide it looks in degrees, above 0 it casts soft shadows.
+    Directional {
+        direction: Float3,
+        #[serde(default, skip_serializing_if = "crate::is_default")]
+        angle: f64,
+    },
+    /// Shines from `center` in a cone around `direction`. It's full strength
+    /// out to `inner_angle` from the direction and fades to nothing at
+    /// `outer_angle` (in degrees), the fade is raised to `exponent`.
+    Spot {
+        direction: Float3,
+        inner_angle: f64,
+        outer_angle: f64,
+        #[serde(default = "default_spot_exponent", skip_serializing_if = "is_default_spot_exponent")]
+        exponent: f64,
+    },
+}
+
+/// How a light gets dimmer with distance.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum Falloff {
+    /// The same brightness at any distance.
+    #[default]
+    None,
+    /// 1 / distance², how real lights behave.
+    InverseSquare,
+    /// 1 / (constant + linear * distance + quadratic * distance²).
+    Polynomial { constant: f64, linear: f64, quadratic: f64 },
+}
+
+impl Falloff {
+    /// How much of the light is left `distance` away.
+    pub fn factor(&self, distance: f64) -> f64 {
+        match *self {
+            Falloff::None => 1.0,
+            Falloff::InverseSquare => 1.0 / (distance * distance),
+            Falloff::Polynomial {
+                constant,
+                linear,
+                quadratic,
+            } => 1.0 / (constant + (linear * distance) + (quadratic * distance * distance)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Light {
+    #[serde(default, skip_serializing_if = "crate::is_default")]
+    pub kind: LightKind,
+
+    /// Where point & spot lights are, directional lights don't have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub center: Option<Float3>,
+    /// Point & spot lights are spheres this big, which softens their shadows.
+    #[serde(default, skip_serializing_if = "crate::is_default")]
+    pub radius: f64,
+    pub color: Float3,
+
+    /// Not used by directional lights.
+    #[serde(default, skip_serializing_if = "crate::is_default")]
+    pub falloff: Falloff,
+
+    /// Overrides the scene's `shadow_samples` for this light.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadow_samples: Option<u32>,
+}
+
+impl Light {
+    /// Describes what's wrong if this can't be rendered, along with the field
+    /// at fault.
+    pub fn problem(&self) -> Option<(&'static str, String)> {
+        if self.radius < 0.0 || !self.radius.is_finite() {
+            return Some(("radius", format!("can't be negative, found {}", self.radius)));
+        }
+
+        if self.shadow_samples == Some(0) {
+            return Some(("shadow_samples", "must be at least 1".to_string()));
+        }
+
+        match self.kind {
+            LightKind::Directional { .. } => {}
+            _ if self.center.is_none() => {
+                return Some(("center", "is needed by point and spot lights".to_string()));
+            }
+            _ => {}
+        }
+
+        // Paths are as serialized, with the kind's name in them.
+        match self.kind {
+            LightKind::Point => {}
+            LightKind::Directional { direction, angle } => {
+                if direction.norm() == 0.0 {
+                    return Some(("kind.Directional.direction", "can't be zero".to_string()));
+                }
+
+                if !(0.0..180.0).contains(&angle) {
+                    return Some(("kind.Directional.angle", format!("must be between 0 and 180 degrees, found {}", angle)));
+                }
+            }
+            LightKind::Spot {
+                direction,
+                inner_angle,
+                outer_angle,
+                exponent,
+            } => {
+                if direction.norm() == 0.0 {
+                    return Some(("kind.Spot.direction", "can't be zero".to_string()));
+                }
+
+                if !(0.0..=180.0).contains(&outer_angle) {
+                    return Some((
+                        "kind.Spot.outer_angle",
+                        format!("must be between 0 and 180 degrees, found {}", outer_angle),
+                    ));
+                }
+
+                if !(0.0..=outer_angle).contains(&inner_angle) {
+                    return Some((
+                        "kind.Spot.inner_angle",
+                        format!("must be between 0 and `outer_angle` degrees, found {}", inner_angle),
+                    ));
+                }
+
+                if exponent <= 0.0 || !exponent.is_finite() {
+                    return Some(("kind.Spot.exponent", format!("must be greater than zero, found {}", exponent)));
+                }
+            }
+        }
+
+        if let Falloff::Polynomial {
+            constant,
+            linear,
+            quadratic,
+        } = self.falloff
+        {
+            let coefficients = [constant, linear, quadratic];
+
+            if coefficients.iter().any(|c| *c < 0.0 || !c.is_finite()) || coefficients.iter().all(|c| *c == 0.0) {
+                return Some((
+                    "falloff",
+                    "coefficients can't be negative and at least one has to be above zero".to_string(),
+                ));
+            }
+        }
+
+        None
+    }
+}
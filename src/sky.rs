@@ -0,0 +1,452 @@
+use serde::{Serialize, Deserialize};
+
+use std::f64::consts::PI;
+
+use super::shapes::{Falloff, Light, LightKind};
+use super::Float3;
+
+fn default_turbidity() -> f64 {
+    3.0
+}
+
+fn default_intensity() -> f64 {
+    1.0
+}
+
+fn default_ground_albedo() -> f64 {
+    0.3
+}
+
+fn is_default_turbidity(turbidity: &f64) -> bool {
+    *turbidity == default_turbidity()
+}
+
+fn is_default_intensity(intensity: &f64) -> bool {
+    *intensity == default_intensity()
+}
+
+fn is_default_ground_albedo(ground_albedo: &f64) -> bool {
+    *ground_albedo == default_ground_albedo()
+}
+
+/// Preetham's model is in kcd/m², this brings a white surface in the midday
+/// sun to about 1.
+const SKY_SCALE: f64 = 0.025;
+
+/// Illuminance of the sun outside the atmosphere in klux.
+const SUN_ILLUMINANCE: f64 = 128.0;
+
+/// How wide the sun looks in degrees.
+const SUN_ANGLE: f64 = 0.53;
+
+/// Where the sun is. Scenes have +y up, -z north and +x east.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum SunPosition {
+    /// Pointing towards the sun.
+    Direction(Float3),
+    /// Where the sun is at `solar_time` hours (12 is noon) on `day_of_year`
+    /// (1 is January 1st) at `latitude` degrees north.
+    Time {
+        day_of_year: u32,
+        solar_time: f64,
+        latitude: f64,
+    },
+}
+
+impl SunPosition {
+    /// Unit vector pointing towards the sun.
+    /// https://doi.org/10.1145/311535.311545 (appendix A.6)
+    pub fn direction(&self) -> Float3 {
+        match *self {
+            SunPosition::Direction(direction) => direction.normalize(),
+            SunPosition::Time {
+                day_of_year,
+                solar_time,
+                latitude,
+            } => {
+                let latitude = latitude.to_radians();
+                let declination = 0.4093 * (2.0 * PI * ((day_of_year as f64) - 81.0) / 368.0).sin();
+                let hour_angle = PI * solar_time / 12.0;
+
+                let zenith = (PI / 2.0)
+                    - ((latitude.sin() * declination.sin())
+                        - (latitude.cos() * declination.cos() * hour_angle.cos()))
+                    .asin();
+
+                // From south, positive towards the west.
+                let azimuth = (-declination.cos() * hour_angle.sin()).atan2(
+                    (latitude.cos() * declination.sin()) - (latitude.sin() * declination.cos() * hour_angle.cos()),
+                );
+
+                Float3::new(
+                    -zenith.sin() * azimuth.sin(),
+                    zenith.cos(),
+                    zenith.sin() * azimuth.cos(),
+                )
+            }
+        }
+    }
+}
+
+/// A clear sky lit by the sun using Preetham et al's model, "A Practical
+/// Analytic Model for Daylight". It comes with a directional light for the
+/// sun itself.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Sky {
+    pub sun: SunPosition,
+    /// How hazy the air is, 2 is very clear and 10 is hazy.
+    #[serde(default = "default_turbidity", skip_serializing_if = "is_default_turbidity")]
+    pub turbidity: f64,
+    /// Scales the sky and sun together.
+    #[serde(default = "default_intensity", skip_serializing_if = "is_default_intensity")]
+    pub intensity: f64,
+    /// Below the horizon is the horizon's color darkened by this.
+    #[serde(default = "default_ground_albedo", skip_serializing_if = "is_default_ground_albedo")]
+    pub ground_albedo: f64,
+}
+
+impl Sky {
+    /// Same as `Environment::problem`.
+    pub fn problem(&self) -> Option<(&'static str, String)> {
+        match self.sun {
+            SunPosition::Direction(direction) if direction.norm() == 0.0 => {
+                return Some(("sun", "direction can't be zero".to_string()));
+            }
+            SunPosition::Time {
+                day_of_year,
+                solar_time,
+                latitude,
+            } => {
+                if !(1..=366).contains(&day_of_year) {
+                    return Some(("sun.day_of_year", format!("must be between 1 and 366, found {}", day_of_year)));
+                }
+
+                if !(0.0..=24.0).contains(&solar_time) {
+                    return Some(("sun.solar_time", format!("must be between 0 and 24, found {}", solar_time)));
+                }
+
+                if !(-90.0..=90.0).contains(&latitude) {
+                    return Some(("sun.latitude", format!("must be between -90 and 90, found {}", latitude)));
+                }
+            }
+            _ => {}
+        }
+
+        // Outside of this the model's fit goes wrong.
+        if !(1.7..=10.0).contains(&self.turbidity) {
+            return Some(("turbidity", format!("must be between 1.7 and 10, found {}", self.turbidity)));
+        }
+
+        if self.intensity < 0.0 || !self.intensity.is_finite() {
+            return Some(("intensity", format!("can't be negative, found {}", self.intensity)));
+        }
+
+        if !(0.0..=1.0).contains(&self.ground_albedo) {
+            return Some((
+                "ground_albedo",
+                format!("must be between 0 and 1, found {}", self.ground_albedo),
+            ));
+        }
+
+        None
+    }
+}
+
+/// The coefficients of the Perez function for one of Y, x or y.
+#[derive(Debug, Copy, Clone)]
+struct Perez([f64; 5]);
+
+impl Perez {
+    fn new(turbidity: f64, coefficients: [[f64; 2]; 5]) -> Self {
+        Perez(coefficients.map(|[t, c]| (t * turbidity) + c))
+    }
+
+    // `theta` from the zenith and `gamma` from the sun.
+    fn value(&self, cos_theta: f64, gamma: f64) -> f64 {
+        let [a, b, c, d, e] = self.0;
+
+        (1.0 + (a * (b / cos_theta).exp())) * (1.0 + (c * (d * gamma).exp()) + (e * gamma.cos() * gamma.cos()))
+    }
+}
+
+// Zenith chromaticity polynomials, rows are T², T and 1, columns θs³, θs², θs
+// and 1.
+const ZENITH_X: [[f64; 4]; 3] = [
+    [0.00166, -0.00375, 0.00209, 0.0],
+    [-0.02903, 0.06377, -0.03202, 0.00394],
+    [0.11693, -0.21196, 0.06052, 0.25886],
+];
+
+const ZENITH_Y: [[f64; 4]; 3] = [
+    [0.00275, -0.00610, 0.00317, 0.0],
+    [-0.04214, 0.08970, -0.04153, 0.00516],
+    [0.15346, -0.26756, 0.06670, 0.26688],
+];
+
+fn zenith_chromaticity(table: &[[f64; 4]; 3], turbidity: f64, theta_sun: f64) -> f64 {
+    let thetas = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+    let turbidities = [turbidity * turbidity, turbidity, 1.0];
+
+    table
+        .iter()
+        .zip(turbidities.iter())
+        .map(|(row, t)| t * row.iter().zip(thetas.iter()).map(|(c, theta)| c * theta).sum::<f64>())
+        .sum()
+}
+
+/// https://en.wikipedia.org/wiki/SRGB#From_CIE_XYZ_to_sRGB
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Float3 {
+    let big_x = (x / y) * luminance;
+    let big_z = ((1.0 - x - y) / y) * luminance;
+
+    Float3::new(
+        (3.2406 * big_x) - (1.5372 * luminance) - (0.4986 * big_z),
+        (-0.9689 * big_x) + (1.8758 * luminance) + (0.0415 * big_z),
+        (0.0557 * big_x) - (0.2040 * luminance) + (1.0570 * big_z),
+    )
+}
+
+/// A `Sky` with everything that only depends on the sun worked out.
+#[derive(Debug, Copy, Clone)]
+pub struct SkyModel {
+    sky: Sky,
+    sun_direction: Float3,
+    /// `sun_direction` kept on or above the horizon, the model only works
+    /// for daylight.
+    sky_sun: Float3,
+
+    perez: [Perez; 3],
+    /// Y, x & y at the zenith divided by the Perez function there.
+    zenith: [f64; 3],
+}
+
+impl SkyModel {
+    pub fn new(sky: &Sky) -> Self {
+        let sun_direction = sky.sun.direction();
+        let sky_sun = Float3::new(sun_direction.x, sun_direction.y.max(0.0), sun_direction.z);
+        let sky_sun = if sky_sun.norm() > 0.0 { sky_sun.normalize() } else { Float3::new(0.0, 1.0, 0.0) };
+        let theta_sun = sky_sun.y.clamp(0.0, 1.0).acos();
+        let t = sky.turbidity;
+
+        let perez = [
+            Perez::new(
+                t,
+                [[0.1787, -1.4630], [-0.3554, 0.4275], [-0.0227, 5.3251], [0.1206, -2.5771], [-0.0670, 0.3703]],
+            ),
+            Perez::new(
+                t,
+                [[-0.0193, -0.2592], [-0.0665, 0.0008], [-0.0004, 0.2125], [-0.0641, -0.8989], [-0.0033, 0.0452]],
+            ),
+            Perez::new(
+                t,
+                [[-0.0167, -0.2608], [-0.0950, 0.0092], [-0.0079, 0.2102], [-0.0441, -1.6537], [-0.0109, 0.0529]],
+            ),
+        ];
+
+        let chi = ((4.0 / 9.0) - (t / 120.0)) * (PI - (2.0 * theta_sun));
+        let zenith_luminance = ((4.0453 * t) - 4.9710) * chi.tan() - (0.2155 * t) + 2.4192;
+
+        let zenith_values = [
+            zenith_luminance,
+            zenith_chromaticity(&ZENITH_X, t, theta_sun),
+            zenith_chromaticity(&ZENITH_Y, t, theta_sun),
+        ];
+
+        let mut zenith = [0.0; 3];
+        for i in 0..3 {
+            zenith[i] = zenith_values[i] / perez[i].value(1.0, theta_sun);
+        }
+
+        SkyModel {
+            sky: *sky,
+            sun_direction,
+            sky_sun,
+            perez,
+            zenith,
+        }
+    }
+
+    /// Light arriving from `direction` (a unit vector), not counting the sun.
+    pub fn radiance(&self, direction: &Float3) -> Float3 {
+        // Below the horizon, darkened.
+        let (direction, scale) = if direction.y < 0.001 {
+            let horizontal = Float3::new(direction.x, 0.0, direction.z);
+            let horizontal = if horizontal.norm() > 0.0 {
+                horizontal.normalize()
+            } else {
+                Float3::new(0.0, 0.0, -1.0)
+            };
+
+            (Float3::new(horizontal.x * 0.9999995, 0.001, horizontal.z * 0.9999995), self.sky.ground_albedo)
+        } else {
+            (*direction, 1.0)
+        };
+
+        let gamma = direction.dot(&self.sky_sun).clamp(-1.0, 1.0).acos();
+
+        let [luminance, x, y] = [0, 1, 2].map(|i| self.zenith[i] * self.perez[i].value(direction.y, gamma));
+
+        xyy_to_rgb(x, y, luminance).map(|c| c.max(0.0)) * (SKY_SCALE * self.sky.intensity * scale)
+    }
+
+    /// A directional light for the sun, colored by the air it passes through.
+    /// `None` when it's below the horizon.
+    pub fn sun(&self) -> Option<Light> {
+        if self.sun_direction.y <= 0.0 {
+            return None;
+        }
+
+        // Relative optical mass, how much air the light goes through compared
+        // to straight down.
+        let zenith_degrees = self.sun_direction.y.acos().to_degrees();
+        let mass = 1.0 / (self.sun_direction.y + (0.15 * (93.885 - zenith_degrees).powf(-1.253)));
+
+        // Rayleigh & aerosol (Ångström) scattering for red, green and blue
+        // wavelengths in μm.
+        let beta = (0.04608 * self.sky.turbidity) - 0.04586;
+        let transmittance = |wavelength: f64| {
+            let rayleigh = (-0.008735 * wavelength.powf(-4.08) * mass).exp();
+            let aerosol = (-beta * wavelength.powf(-1.3) * mass).exp();
+
+            rayleigh * aerosol
+        };
+
+        // Lights are scaled so a white surface facing them gets their color,
+        // where the sky gives 1/π of the irradiance.
+        let scale = SUN_ILLUMINANCE * SKY_SCALE * self.sky.intensity / PI;
+
+        Some(Light {
+            kind: LightKind::Directional {
+                direction: -self.sun_direction,
+                angle: SUN_ANGLE,
+            },
+            center: None,
+            radius: 0.0,
+            color: Float3::new(transmittance(0.65), transmittance(0.57), transmittance(0.475)) * scale,
+            falloff: Falloff::None,
+            shadow_samples: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sky(sun: SunPosition, turbidity: f64) -> Sky {
+        Sky {
+            sun,
+            turbidity,
+            intensity: default_intensity(),
+            ground_albedo: default_ground_albedo(),
+        }
+    }
+
+    // `azimuth` is in degrees east of north.
+    fn from_angles(elevation: f64, azimuth: f64) -> Float3 {
+        let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+
+        Float3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        )
+    }
+
+    fn assert_close(a: Float3, b: Float3, tolerance: f64) {
+        assert!((a - b).norm() < tolerance, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn radiance_is_finite_and_not_negative() {
+        let suns = [
+            SunPosition::Direction(from_angles(90.0, 0.0)),
+            SunPosition::Direction(from_angles(35.0, 120.0)),
+            SunPosition::Direction(from_angles(2.0, 270.0)),
+            SunPosition::Direction(from_angles(0.0, 45.0)),
+            SunPosition::Direction(from_angles(-20.0, 200.0)),
+        ];
+
+        for sun in &suns {
+            for &turbidity in &[1.7, 3.0, 6.0, 10.0] {
+                let model = SkyModel::new(&sky(*sun, turbidity));
+
+                // Every 5 degrees around and from straight down to straight
+                // up, right through the sun.
+                for elevation in -18..=18 {
+                    for azimuth in 0..72 {
+                        let direction = from_angles(elevation as f64 * 5.0, azimuth as f64 * 5.0);
+                        let radiance = model.radiance(&direction);
+
+                        assert!(
+                            radiance.iter().all(|c| c.is_finite() && *c >= 0.0),
+                            "{:?} at turbidity {} towards {:?}: {:?}",
+                            sun,
+                            turbidity,
+                            direction,
+                            radiance
+                        );
+                    }
+                }
+
+                assert!(model.radiance(&sun.direction()).iter().all(|c| c.is_finite() && *c >= 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn sun_follows_its_elevation_and_azimuth() {
+        for &(elevation, azimuth) in &[(90.0, 0.0), (80.0, 0.0), (30.0, 90.0), (15.0, 200.0), (5.0, 300.0)] {
+            let direction = from_angles(elevation, azimuth);
+            let model = SkyModel::new(&sky(SunPosition::Direction(direction * 3.0), 3.0));
+
+            match model.sun().unwrap().kind {
+                LightKind::Directional { direction: light, angle } => {
+                    assert_close(light, -direction, 1e-12);
+                    assert_eq!(angle, SUN_ANGLE);
+                }
+                kind => panic!("the sun should be directional, not {:?}", kind),
+            }
+
+            // The sky is brighter around the sun than opposite it.
+            if elevation == 90.0 {
+                continue;
+            }
+
+            let near = model.radiance(&from_angles(elevation - 3.0, azimuth)).sum();
+            let away = model.radiance(&from_angles(elevation - 3.0, azimuth + 180.0)).sum();
+            assert!(near > away, "{} <= {} for the sun at {}, {}", near, away, elevation, azimuth);
+        }
+
+        // Below the horizon there's no sun, only some sky.
+        let model = SkyModel::new(&sky(SunPosition::Direction(from_angles(-10.0, 90.0)), 3.0));
+        assert!(model.sun().is_none());
+    }
+
+    #[test]
+    fn sun_follows_the_time_of_day() {
+        // Around the March equinox the sun is over the equator, so at noon
+        // it's as far from straight up as the latitude, to the south in the
+        // north and the other way around.
+        let at = |day_of_year, solar_time, latitude| {
+            SunPosition::Time {
+                day_of_year,
+                solar_time,
+                latitude,
+            }
+            .direction()
+        };
+
+        assert_close(at(81, 12.0, 0.0), from_angles(90.0, 0.0), 1e-9);
+        assert_close(at(81, 12.0, 40.0), from_angles(50.0, 180.0), 1e-9);
+        assert_close(at(81, 12.0, -40.0), from_angles(50.0, 0.0), 1e-9);
+
+        // It rises in the east and sets in the west.
+        assert_close(at(81, 6.0, 40.0), from_angles(0.0, 90.0), 1e-9);
+        assert_close(at(81, 18.0, 40.0), from_angles(0.0, 270.0), 1e-9);
+
+        // At the June solstice it's overhead at noon on the tropic of Cancer.
+        assert!(at(172, 12.0, 23.44).y > 0.99999);
+        assert!(at(172, 0.0, 50.0).y < 0.0);
+    }
+}
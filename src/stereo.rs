@@ -0,0 +1,189 @@
+use serde::{Serialize, Deserialize};
+
+use std::str::FromStr;
+
+use super::camera::Camera;
+use super::image::ImageBuffer;
+use super::render::Canvas;
+use super::Float3;
+
+/// How the two eyes are written out.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum StereoLayout {
+    /// One file per eye.
+    Separate,
+    /// Left eye on the left, right eye on the right, twice as wide.
+    #[default]
+    SideBySide,
+    /// Left eye on top, right eye below, twice as tall.
+    OverUnder,
+    /// Red-cyan, red from the left eye and green & blue from the right.
+    Anaglyph,
+}
+
+impl FromStr for StereoLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = crate::normalize_name(s);
+
+        match name.as_str() {
+            "separate" => Ok(StereoLayout::Separate),
+            "sidebyside" => Ok(StereoLayout::SideBySide),
+            "overunder" => Ok(StereoLayout::OverUnder),
+            "anaglyph" => Ok(StereoLayout::Anaglyph),
+            _ => Err(format!("Unknown stereo layout '{}'", s)),
+        }
+    }
+}
+
+/// Renders the scene once for each eye.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Stereo {
+    /// Distance between the eyes in scene units.
+    pub interocular_distance: f64,
+    /// Distance in front of the camera where the eyes' views line up, things
+    /// there appear at the depth of the screen.
+    pub convergence_distance: f64,
+    #[serde(default, skip_serializing_if = "crate::is_default")]
+    pub layout: StereoLayout,
+}
+
+impl Stereo {
+    /// Describes what's wrong if this can't be rendered, along with the field
+    /// at fault.
+    pub fn problem(&self) -> Option<(&'static str, String)> {
+        if self.interocular_distance <= 0.0 || !self.interocular_distance.is_finite() {
+            return Some((
+                "interocular_distance",
+                format!("must be greater than zero, found {}", self.interocular_distance),
+            ));
+        }
+
+        if self.convergence_distance <= 0.0 || !self.convergence_distance.is_finite() {
+            return Some((
+                "convergence_distance",
+                format!("must be greater than zero, found {}", self.convergence_distance),
+            ));
+        }
+
+        None
+    }
+
+    /// The left and right eye's cameras.
+    pub fn cameras(&self, camera: &Camera) -> [Camera; 2] {
+        let offset = self.interocular_distance * 0.5;
+
+        [
+            camera.shifted(-offset, self.convergence_distance),
+            camera.shifted(offset, self.convergence_distance),
+        ]
+    }
+}
+
+/// Combines the two eyes into one image, `None` for `StereoLayout::Separate`.
+pub fn compose(left: &ImageBuffer, right: &ImageBuffer, layout: StereoLayout) -> Option<ImageBuffer> {
+    let (width, height) = (left.width, left.height);
+
+    let mut out = match layout {
+        StereoLayout::Separate => return None,
+        StereoLayout::SideBySide => ImageBuffer::new(width * 2, height),
+        StereoLayout::OverUnder => ImageBuffer::new(width, height * 2),
+        StereoLayout::Anaglyph => ImageBuffer::new(width, height),
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            let l = left.get_pixel(x, y);
+            let r = right.get_pixel(x, y);
+
+            match layout {
+                StereoLayout::SideBySide => {
+                    out.set_pixel(x, y, &l);
+                    out.set_pixel(x + width, y, &r);
+                }
+                StereoLayout::OverUnder => {
+                    // y = 0 is the bottom row.
+                    out.set_pixel(x, y + height, &l);
+                    out.set_pixel(x, y, &r);
+                }
+                StereoLayout::Anaglyph => out.set_pixel(x, y, &Float3::new(l.x, r.y, r.z)),
+                StereoLayout::Separate => unreachable!(),
+            }
+        }
+    }
+
+    Some(out)
+}
+
+/// Filename for one eye when writing them separately, `out.png` becomes
+/// `out-left.png`.
+pub fn eye_filename(filename: &str, eye: &str) -> String {
+    match filename.rfind('.') {
+        Some(dot) if !filename[dot..].contains('/') => format!("{}-{}{}", &filename[..dot], eye, &filename[dot..]),
+        _ => format!("{}-{}", filename, eye),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::camera::{LookAt, Projection};
+
+    fn camera() -> Camera {
+        let look_at = LookAt {
+            eye: Float3::new(1.0, 2.0, 3.0),
+            target: Float3::new(3.0, 1.0, -2.0),
+            up: Float3::new(0.0, 1.0, 0.0),
+            fov: 60.0,
+            aspect_ratio: None,
+        };
+
+        Camera::from_look_at(&look_at, 1.5)
+    }
+
+    fn stereo() -> Stereo {
+        Stereo {
+            interocular_distance: 0.2,
+            convergence_distance: 5.0,
+            layout: StereoLayout::default(),
+        }
+    }
+
+    fn assert_close(a: Float3, b: Float3) {
+        assert!((a - b).norm() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn eyes_are_apart_along_the_right_vector() {
+        for &projection in &[Projection::Perspective, Projection::Orthographic { height: 4.0 }] {
+            let camera = Camera { projection, ..camera() };
+            let right = camera.viewport_x_axis.normalize();
+            let [left_eye, right_eye] = stereo().cameras(&camera);
+
+            assert_close(left_eye.eye, camera.eye - (right * 0.1));
+            assert_close(right_eye.eye, camera.eye + (right * 0.1));
+
+            // Both still look the same way.
+            for eye in &[left_eye, right_eye] {
+                assert_close(eye.viewport_x_axis, camera.viewport_x_axis);
+                assert_close(eye.viewport_y_axis, camera.viewport_y_axis);
+            }
+        }
+    }
+
+    #[test]
+    fn eyes_converge_at_the_convergence_distance() {
+        let camera = camera();
+        let forward = (camera.viewport_origin - camera.eye).normalize();
+        let meeting_point = camera.eye + (forward * 5.0);
+
+        for eye in &stereo().cameras(&camera) {
+            let ray = eye.ray(0.0, 0.0, (0.5, 0.5)).unwrap();
+            let distance = (meeting_point - ray.origin).dot(&forward) / ray.direction.dot(&forward);
+
+            assert_close(ray.origin + (ray.direction * distance), meeting_point);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::str::FromStr;
+
+use super::Float3;
+
+/// Order in which tiles are handed out to the render threads.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TileOrder {
+    /// Rows of tiles from the top of the image to the bottom.
+    Scanline,
+    /// Rings of tiles spiralling out from the center of the image.
+    Spiral,
+    /// Along a Hilbert curve, keeps consecutive tiles close together.
+    Hilbert,
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match crate::normalize_name(s).as_str() {
+            "scanline" => Ok(TileOrder::Scanline),
+            "spiral" => Ok(TileOrder::Spiral),
+            "hilbert" => Ok(TileOrder::Hilbert),
+            _ => Err(format!("Unknown tile order '{}'", s)),
+        }
+    }
+}
+
+/// Rectangular block of the image, (x,y) is the bottom left pixel.
+#[derive(Debug, Clone)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+
+    /// Row major, starting at the bottom row.
+    pub pixels: Vec<Float3>,
+}
+
+impl Tile {
+    fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Tile {
+            x,
+            y,
+            width,
+            height,
+            pixels: Vec::new(),
+        }
+    }
+}
+
+/// https://en.wikipedia.org/wiki/Hilbert_curve#Applications_and_mapping_algorithms
+fn hilbert_index(n: u32, x: u32, y: u32) -> u64 {
+    let mut x = x;
+    let mut y = y;
+    let mut d = 0u64;
+
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = ((x & s) > 0) as u32;
+        let ry = ((y & s) > 0) as u32;
+
+        d += (s as u64) * (s as u64) * ((3 * rx) ^ ry) as u64;
+
+        // Rotate the quadrant so the curve stays continuous.
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        s /= 2;
+    }
+
+    d
+}
+
+/// Splits a `width` x `height` image into tiles of at most `tile_size`
+/// pixels square, sorted in the given order.
+pub fn create_tiles(width: u32, height: u32, tile_size: u32, order: TileOrder) -> Vec<Tile> {
+    let tiles_x = width.div_ceil(tile_size);
+    let tiles_y = height.div_ceil(tile_size);
+
+    let mut tiles = Vec::with_capacity((tiles_x * tiles_y) as usize);
+    let mut keys = Vec::with_capacity(tiles.capacity());
+
+    let center_x = (tiles_x as f64 - 1.0) / 2.0;
+    let center_y = (tiles_y as f64 - 1.0) / 2.0;
+    let hilbert_size = u32::max(tiles_x, tiles_y).next_power_of_two();
+
+    for ty in 0..tiles_y {
+        for tx in 0..tiles_x {
+            let x = tx * tile_size;
+            let y = ty * tile_size;
+
+            tiles.push(Tile::new(
+                x,
+                y,
+                u32::min(tile_size, width - x),
+                u32::min(tile_size, height - y),
+            ));
+
+            let key = match order {
+                // Tile row 0 is the bottom of the image, start at the top.
+                TileOrder::Scanline => ((tiles_y - 1 - ty) as f64, tx as f64),
+                TileOrder::Spiral => {
+                    let dx = tx as f64 - center_x;
+                    let dy = ty as f64 - center_y;
+                    let ring = f64::max(dx.abs(), dy.abs()).round();
+
+                    (ring, dy.atan2(dx))
+                }
+                TileOrder::Hilbert => (hilbert_index(hilbert_size, tx, ty) as f64, 0.0),
+            };
+
+            keys.push(key);
+        }
+    }
+
+    let mut indices: Vec<usize> = (0..tiles.len()).collect();
+    indices.sort_by(|a, b| keys[*a].partial_cmp(&keys[*b]).unwrap());
+
+    indices.into_iter().map(|i| tiles[i].clone()).collect()
+}